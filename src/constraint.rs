@@ -0,0 +1,240 @@
+use crate::error::*;
+use crate::format::*;
+
+use regex::Regex;
+use std::cmp::Ordering;
+
+// A rule on the values of a key, declared by `@ CHECK(Class$key, constraint)`:
+// a comparison, `population > 0` or `0 <= size <= 1`, a pattern, `code ~ ^[A-Z]{3}$`,
+// or a length, `len(languages) <= 5`, counting the items of a list or the characters of a string.
+// Comparisons and patterns apply to each item of a list; missing values satisfy any constraint.
+#[derive(Debug, Clone)]
+pub struct Constraint {
+    text: String,
+    rule: Rule,
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Compare(Vec<(Operand, Comparison, Operand)>), // `0 <= size <= 1` as `0 <= size` and `size <= 1`
+    Pattern(Regex),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Key,
+    Length,
+    Literal(String), // a number or a date
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Constraint {
+    // `None` unless a valid constraint on `key`
+    pub fn parse(key: &str, text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Some((left, pattern)) = text.split_once('~') {
+            if left.trim() != key {
+                return None;
+            }
+            let pattern = Regex::new(pattern.trim()).ok()?;
+            return Some(Self { text: text.to_string(), rule: Rule::Pattern(pattern) });
+        }
+
+        let (words, comparisons) = split_comparisons(text);
+        let length = format!("len({})", key);
+        let mut operands = Vec::new();
+        for word in words {
+            operands.push(match word {
+                "" => return None,
+                w if w == key => Operand::Key,
+                w if w == length => Operand::Length,
+                w if is_literal(w) => Operand::Literal(w.to_string()),
+                _ => return None,
+            });
+        }
+        // each comparison relates the key to a literal, or its length to a number
+        let pairs: Vec<(Operand, Comparison, Operand)> = comparisons
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| (operands[i].clone(), c, operands[i + 1].clone()))
+            .collect();
+        let valid = |(l, _, r): &(Operand, Comparison, Operand)| match (l, r) {
+            (Operand::Literal(_), Operand::Key) | (Operand::Key, Operand::Literal(_)) => true,
+            (Operand::Literal(n), Operand::Length) | (Operand::Length, Operand::Literal(n)) => is_num(n),
+            _ => false,
+        };
+        if pairs.is_empty() || !pairs.iter().all(valid) {
+            return None;
+        }
+        Some(Self { text: text.to_string(), rule: Rule::Compare(pairs) })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // the value, or the item of a list, breaking the constraint
+    pub fn violation<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        if value.is_na() {
+            return None;
+        }
+        let items: Vec<&Value> = match value.as_list() {
+            Some(list) => list.items().iter().filter(|item| !item.is_na()).collect(),
+            None => vec![value],
+        };
+        let pairs = match &self.rule {
+            Rule::Pattern(pattern) => return items.into_iter().find(|item| !pattern.is_match(&item.get_raw())),
+            Rule::Compare(pairs) => pairs,
+        };
+        for (left, comparison, right) in pairs {
+            if *left == Operand::Length || *right == Operand::Length {
+                let length = match value.as_list() {
+                    Some(list) => list.items().len(),
+                    None => value.get_raw().chars().count(),
+                };
+                let number = |operand: &Operand| match operand {
+                    Operand::Literal(l) => Decimal::parse(l),
+                    _ => Decimal::parse(&length.to_string()),
+                };
+                if !comparison.holds(number(left), number(right)) {
+                    return Some(value);
+                }
+                continue;
+            }
+            if let Some(item) = items.iter().find(|item| !holds_for(item, left, *comparison, right)) {
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+// dates compare at the precision of their type; intervals compare by both bounds
+fn holds_for(value: &Value, left: &Operand, comparison: Comparison, right: &Operand) -> bool {
+    if value.typ().is_date() {
+        let key = |operand: &Operand| match operand {
+            Operand::Literal(l) => date_key(l, value.typ()),
+            _ => date_key(&value.get_raw(), value.typ()),
+        };
+        return comparison.holds(key(left), key(right));
+    }
+    let (lower, upper, _) = match value.bounds() {
+        Some(bounds) => bounds,
+        None => return false,
+    };
+    [lower, upper].iter().all(|bound| {
+        let number = |operand: &Operand| match operand {
+            Operand::Literal(l) => Decimal::parse(l),
+            _ => Some(*bound),
+        };
+        comparison.holds(number(left), number(right))
+    })
+}
+
+impl Comparison {
+    // `false` if either side is not a number or date
+    fn holds<T: Ord>(&self, left: Option<T>, right: Option<T>) -> bool {
+        let ordering = match (left, right) {
+            (Some(l), Some(r)) => l.cmp(&r),
+            _ => return false,
+        };
+        match self {
+            Self::Lt => ordering == Ordering::Less,
+            Self::Le => ordering != Ordering::Greater,
+            Self::Gt => ordering == Ordering::Greater,
+            Self::Ge => ordering != Ordering::Less,
+            Self::Eq => ordering == Ordering::Equal,
+            Self::Ne => ordering != Ordering::Equal,
+        }
+    }
+}
+
+// a number, or a date of any precision, e.g. `1999-01`
+fn is_literal(word: &str) -> bool {
+    let dates = [ValueType::Year, ValueType::YearMonth, ValueType::Date, ValueType::DateTime];
+    is_num(word) || dates.iter().any(|typ| is_date(word, typ))
+}
+
+// `0 <= size <= 1` as `["0", "size", "1"]` and `[Le, Le]`
+fn split_comparisons(text: &str) -> (Vec<&str>, Vec<Comparison>) {
+    let mut words = Vec::new();
+    let mut comparisons = Vec::new();
+    let (mut start, mut i) = (0, 0);
+    let bytes = text.as_bytes();
+    while i < bytes.len() {
+        let (comparison, width) = match &bytes[i..] {
+            [b'<', b'=', ..] => (Comparison::Le, 2),
+            [b'>', b'=', ..] => (Comparison::Ge, 2),
+            [b'!', b'=', ..] => (Comparison::Ne, 2),
+            [b'=', b'=', ..] => (Comparison::Eq, 2),
+            [b'<', ..] => (Comparison::Lt, 1),
+            [b'>', ..] => (Comparison::Gt, 1),
+            [b'=', ..] => (Comparison::Eq, 1),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        words.push(text[start..i].trim());
+        comparisons.push(comparison);
+        i += width;
+        start = i;
+    }
+    words.push(text[start..].trim());
+    (words, comparisons)
+}
+
+// Check the values of each entry against the constraints declared on it and its ancestors.
+pub fn validate(root: &Entry) -> Result<(), ReamError> {
+    validate_inner(root, &[])
+}
+
+// the constraints declared on the entry, after those inherited
+pub fn declared(entry: &Entry, inherited: &[(String, String, Constraint)]) -> Vec<(String, String, Constraint)> {
+    let mut constraints = inherited.to_vec();
+    for (class, key, text) in entry.constraints() {
+        if let Some(constraint) = Constraint::parse(&key, &text) {
+            constraints.push((class, key, constraint));
+        }
+    }
+    constraints
+}
+
+fn validate_inner(entry: &Entry, inherited: &[(String, String, Constraint)]) -> Result<(), ReamError> {
+    let constraints = declared(entry, inherited);
+    check(entry, &constraints)?;
+    for subentry in entry.subentries() {
+        validate_inner(subentry, &constraints)?;
+    }
+    Ok(())
+}
+
+// the first value of the entry breaking a constraint on its class, as an error
+pub fn check(entry: &Entry, constraints: &[(String, String, Constraint)]) -> Result<(), ReamError> {
+    for (class, key, constraint) in constraints {
+        if entry.class() != *class {
+            continue;
+        }
+        let item = match entry.value(key).and_then(|value| constraint.violation(value)) {
+            Some(item) => item,
+            None => continue,
+        };
+        return Err(ReamError::ConstraintError(Box::new(ConstraintViolation {
+            class: class.clone(),
+            key: key.clone(),
+            constraint: constraint.text().to_string(),
+            value: item.get_raw(),
+            span: item.span(),
+        })));
+    }
+    Ok(())
+}
@@ -1,17 +1,142 @@
+use crate::constraint::Constraint;
 use crate::error::*;
+use crate::format::LiteralProfile;
 
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Decorator {
     Ignore,
+    Missing(Vec<String>), // codes for missing values, e.g. `NA(refused)`
+    Id(String),           // identifier of the entry
+    IdKey(String),        // key holding the identifiers of the class
+    ForeignKey(String, String), // key holding identifiers of another class
+    Template(String),     // the entry is a template, not data
+    Use(String),          // the entry is an instance of a template
+    For(String, Vec<String>), // the entry is repeated for each value of the key
+    Default(String, String, String), // class, key and default value, within the entry
+    Parents(Vec<String>), // classes entries of the class may be nested in
+    Recursive,            // the class may be nested in itself, at any depth
+    Levels(String, Vec<String>), // name and ordered levels of a categorical type
+    Literals(String),     // name of the literal profile, e.g. `de`
+    Check(String, String, String), // class, key and constraint on its values, within the entry
 }
 
 impl Decorator {
     pub fn from(raw: String) -> Result<Decorator, ReamError> {
-        match raw.as_str() {
-            "IGNORE" => Ok(Decorator::Ignore),
+        // `CHECK(Class$key, constraint)`; the constraint is kept as written, commas and all
+        if let Some(inner) = raw.trim().strip_prefix("CHECK(").and_then(|r| r.strip_suffix(')')) {
+            return match inner.split_once(',') {
+                Some((target, text)) => match target.trim().split_once('$') {
+                    Some((class, key)) if !class.is_empty() && Constraint::parse(key, text).is_some() => {
+                        Ok(Decorator::Check(class.to_string(), key.to_string(), text.trim().to_string()))
+                    }
+                    _ => Err(ReamError::DecoratorError(DecoratorErrorType::InvalidDecorator)),
+                },
+                None => Err(ReamError::DecoratorError(DecoratorErrorType::InvalidDecorator)),
+            };
+        }
+        let (name, args) = split_args(&raw)?;
+        match (name, args) {
+            ("IGNORE", None) => Ok(Decorator::Ignore),
+            ("RECURSIVE", None) => Ok(Decorator::Recursive),
+            ("PARENTS", Some(classes)) if !classes.is_empty() => Ok(Decorator::Parents(classes)),
+            ("MISSING", Some(codes)) if !codes.is_empty() => Ok(Decorator::Missing(codes)),
+            ("LEVELS", Some(args)) if args.len() >= 2 => Ok(Decorator::Levels(args[0].clone(), args[1..].to_vec())),
+            ("LITERALS", Some(args)) if args.len() == 1 && LiteralProfile::named(&args[0]).is_some() => {
+                Ok(Decorator::Literals(args[0].clone()))
+            }
+            ("ID", Some(args)) if args.len() == 1 => Ok(Decorator::Id(args[0].clone())),
+            ("ID_KEY", Some(args)) if args.len() == 1 => Ok(Decorator::IdKey(args[0].clone())),
+            ("FOREIGN_KEY", Some(args)) if args.len() == 2 => {
+                Ok(Decorator::ForeignKey(args[0].clone(), args[1].clone()))
+            }
+            ("TEMPLATE", Some(args)) if args.len() == 1 => Ok(Decorator::Template(args[0].clone())),
+            ("USE", Some(args)) if args.len() == 1 => Ok(Decorator::Use(args[0].clone())),
+            ("FOR", Some(args)) if args.len() >= 2 && loop_values(&args[1..]).is_some() => {
+                Ok(Decorator::For(args[0].clone(), args[1..].to_vec()))
+            }
+            // `DEFAULT(Class$key, value)`; the value may contain commas
+            ("DEFAULT", Some(args)) if args.len() >= 2 => match args[0].split_once('$') {
+                Some((class, key)) if !class.is_empty() && !key.is_empty() => {
+                    Ok(Decorator::Default(class.to_string(), key.to_string(), args[1..].join(", ")))
+                }
+                _ => Err(ReamError::DecoratorError(DecoratorErrorType::InvalidDecorator)),
+            },
             _ => Err(ReamError::DecoratorError(DecoratorErrorType::InvalidDecorator)),
         }
     }
+
+    // decorators declaring properties of the whole dataset
+    pub fn is_dataset_level(&self) -> bool {
+        matches!(self, Decorator::Missing(_) | Decorator::Levels(_, _) | Decorator::Literals(_))
+    }
+
+    // decorators consumed when templates and loops are expanded
+    pub fn is_generative(&self) -> bool {
+        matches!(self, Decorator::Template(_) | Decorator::Use(_) | Decorator::For(_, _))
+    }
+}
+
+impl fmt::Display for Decorator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ignore => write!(f, "IGNORE"),
+            Self::Missing(codes) => write!(f, "MISSING({})", codes.join(", ")),
+            Self::Id(id) => write!(f, "ID({})", id),
+            Self::IdKey(key) => write!(f, "ID_KEY({})", key),
+            Self::ForeignKey(key, class) => write!(f, "FOREIGN_KEY({}, {})", key, class),
+            Self::Template(name) => write!(f, "TEMPLATE({})", name),
+            Self::Use(name) => write!(f, "USE({})", name),
+            Self::For(key, values) => write!(f, "FOR({}, {})", key, values.join(", ")),
+            Self::Default(class, key, value) => write!(f, "DEFAULT({}${}, {})", class, key, value),
+            Self::Parents(classes) => write!(f, "PARENTS({})", classes.join(", ")),
+            Self::Recursive => write!(f, "RECURSIVE"),
+            Self::Levels(name, levels) => write!(f, "LEVELS({}, {})", name, levels.join(", ")),
+            Self::Literals(name) => write!(f, "LITERALS({})", name),
+            Self::Check(class, key, text) => write!(f, "CHECK({}${}, {})", class, key, text),
+        }
+    }
+}
+
+// Split `NAME(arg1, arg2)` into its name and arguments.
+// Decorators without parentheses have no arguments.
+fn split_args(raw: &str) -> Result<(&str, Option<Vec<String>>), ReamError> {
+    let raw = raw.trim();
+    match raw.find('(') {
+        None => Ok((raw, None)),
+        Some(i) => {
+            let inner = match raw[i + 1..].strip_suffix(')') {
+                Some(inner) => inner,
+                None => return Err(ReamError::DecoratorError(DecoratorErrorType::InvalidDecorator)),
+            };
+            let args = inner
+                .split(',')
+                .map(|arg| arg.trim().to_string())
+                .filter(|arg| !arg.is_empty())
+                .collect();
+            Ok((raw[..i].trim(), Some(args)))
+        }
+    }
+}
+
+// most values a range may give
+const MAX_LOOP_VALUES: i64 = 10_000;
+
+// Values of a loop: an inclusive range of integers, `1990..2020`,
+// or the values themselves.
+pub fn loop_values(args: &[String]) -> Option<Vec<String>> {
+    match args {
+        [range] if range.contains("..") => {
+            let (start, end) = range.split_once("..")?;
+            let start = start.trim().parse::<i64>().ok()?;
+            let end = end.trim().parse::<i64>().ok()?;
+            if start > end || end.checked_sub(start)? >= MAX_LOOP_VALUES {
+                return None;
+            }
+            Some((start..=end).map(|i| i.to_string()).collect())
+        }
+        _ => Some(args.to_vec()),
+    }
 }
@@ -0,0 +1,449 @@
+use crate::constraint::{check, declared, validate};
+use crate::error::*;
+use crate::format::*;
+use crate::parser::*;
+use crate::resolver::{resolve_dependents, resolve_with_dependencies, Dependencies, Node};
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// Replace the bytes in `range` of the source with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+struct Tree {
+    unresolved: Entry, // before references are resolved
+    resolved: Entry,
+    dependencies: Dependencies,
+    blocks: BTreeMap<usize, Block>, // first line -> variable written from it
+}
+
+// The lines of a variable: its `- key` line, its `*` items and its `>` annotation.
+struct Block {
+    end: usize, // last line
+    nodes: Vec<Node>, // more than one for values copied by a template or a loop
+}
+
+// A source kept with its parse tree between edits, for editors.
+//
+// An edit within the lines of a single variable, such as a new `*` item, re-parses
+// that variable alone and patches the tree, moving the entries and values below it
+// if lines were inserted or deleted. References reading it,
+// directly or not, are resolved again, and their values checked against constraints.
+// Any other edit, or one changing the type of the value, falls back to a full
+// parse. Either way the tree equals a full parse of the edited source.
+pub struct Document {
+    source: String,
+    options: ParserOptions,
+    lines: Vec<usize>, // offset of the start of each line
+    tree: Result<Option<Tree>, ReamError>,
+}
+
+impl Document {
+    pub fn new(source: &str) -> Self {
+        Self::with_options(source, ParserOptions::default())
+    }
+
+    pub fn with_options(source: &str, options: ParserOptions) -> Self {
+        let lines = std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        let mut document = Self {
+            source: source.to_string(),
+            options,
+            lines,
+            tree: Ok(None),
+        };
+        document.tree = document.parse();
+        document
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn entry(&self) -> Result<Option<&Entry>, &ReamError> {
+        match &self.tree {
+            Ok(tree) => Ok(tree.as_ref().map(|t| &t.resolved)),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Panics if `range` is out of bounds or not on char boundaries,
+    // as `String::replace_range`.
+    pub fn edit(&mut self, edit: &Edit) -> Result<Option<&Entry>, &ReamError> {
+        let patched = self.patch(edit);
+        self.source.replace_range(edit.range.clone(), &edit.text);
+        let lines = self.lines.len();
+        self.update_lines(edit);
+        if !patched {
+            self.tree = self.parse();
+        } else if self.lines.len() != lines {
+            // the lines of the variables below the edit have moved
+            let mut blocks = BTreeMap::new();
+            if let Ok(Some(tree)) = &self.tree {
+                self.index_blocks(&tree.unresolved, &mut Vec::new(), &mut blocks);
+            }
+            if let Ok(Some(tree)) = &mut self.tree {
+                tree.blocks = blocks;
+            }
+        }
+        self.entry()
+    }
+
+    // Re-parse the edited variable alone, if its key and type are unchanged.
+    // Returns whether the tree was patched.
+    fn patch(&mut self, edit: &Edit) -> bool {
+        let range = edit.range.clone();
+        // lines inserted, or deleted if negative
+        let delta = edit.text.matches('\n').count() as isize - self.source[range.clone()].matches('\n').count() as isize;
+        // an edit ending with a line break, as when deleting a whole line, leaves the next line as it is
+        let whole_lines = range.end > range.start
+            && self.source[..range.end].ends_with('\n')
+            && (edit.text.is_empty() || edit.text.ends_with('\n'));
+        let last = match whole_lines {
+            true => self.line_of(range.end - 1),
+            false => self.line_of(range.end),
+        };
+        let first = self.line_of(range.start);
+        let tree = match &mut self.tree {
+            Ok(Some(tree)) => tree,
+            _ => return false,
+        };
+
+        // the edited lines must be those of exactly one value of the tree,
+        // not one copied by a template or a loop
+        let (start_line, end_line, node) = match tree.blocks.range(..=first).next_back() {
+            Some((&start_line, block)) if last <= block.end => match block.nodes.as_slice() {
+                [node] => (start_line, block.end, node.clone()),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        let start = self.lines[start_line - 1];
+        let end = match self.lines.get(end_line) {
+            Some(next) => next - 1,
+            None => self.source.len(),
+        };
+        let edited = format!(
+            "{}{}{}",
+            &self.source[start..range.start],
+            edit.text,
+            &self.source[range.end.min(end)..end],
+        );
+
+        let (key, value, is_ref) = match parse_variable(&edited, start_line, &tree.unresolved, &self.options) {
+            Some(parsed) => parsed,
+            None => return false,
+        };
+        let (path, current_key) = &node;
+        let entry = match tree.unresolved.entry_at_mut(path) {
+            Some(entry) => entry,
+            None => unreachable!(),
+        };
+        let current = match entry.value(current_key) {
+            Some(value) => value,
+            None => unreachable!(),
+        };
+        // a change of key, type or identifier may break the schema
+        if key.0 != *current_key
+            || key.1 != entry.is_optional(current_key)
+            || key.2 != entry.is_typed(current_key)
+            || current.typ() != value.typ()
+            || current.unit() != value.unit()
+            || *current.provenance() != Provenance::Explicit
+            || entry.id() == Some(&current.get_raw())
+            || is_ref != entry.is_ref_key(current_key)
+        {
+            return false;
+        }
+        let span = value.span();
+        if span.start().line() < start_line || span.end().line() as isize > end_line as isize + delta {
+            return false;
+        }
+        if delta != 0 {
+            tree.unresolved.shift_lines(end_line, delta);
+            tree.resolved.shift_lines(end_line, delta);
+        }
+        let entry = match tree.unresolved.entry_at_mut(path) {
+            Some(entry) => entry,
+            None => unreachable!(),
+        };
+        entry.set_value(current_key.clone(), value.clone());
+
+        // a reference may read other variables than before
+        if is_ref {
+            let mut resolved = tree.unresolved.clone();
+            match resolve_with_dependencies(&mut resolved).and_then(|d| validate(&resolved).map(|()| d)) {
+                Ok(dependencies) => {
+                    tree.resolved = resolved;
+                    tree.dependencies = dependencies;
+                }
+                Err(e) => self.tree = Err(e),
+            }
+            return true;
+        }
+
+        match tree.resolved.entry_at_mut(path) {
+            Some(entry) => entry.set_value(current_key.clone(), value),
+            None => unreachable!(),
+        }
+        let checked = resolve_dependents(&tree.unresolved, &mut tree.resolved, &tree.dependencies, &node)
+            .and_then(|mut nodes| {
+                nodes.push(node.clone());
+                validate_nodes(&tree.resolved, &nodes)
+            });
+        if let Err(e) = checked {
+            self.tree = Err(e);
+        }
+        true
+    }
+
+    fn parse(&self) -> Result<Option<Tree>, ReamError> {
+        let unresolved = match Parser::with_options(&self.source, self.options.clone()).parse_tree()? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut resolved = unresolved.clone();
+        let dependencies = resolve_with_dependencies(&mut resolved)?;
+        validate(&resolved)?;
+        let mut blocks = BTreeMap::new();
+        self.index_blocks(&unresolved, &mut Vec::new(), &mut blocks);
+        Ok(Some(Tree { unresolved, resolved, dependencies, blocks }))
+    }
+
+    // the lines of each variable, from the span of its value
+    fn index_blocks(&self, entry: &Entry, path: &mut Vec<usize>, blocks: &mut BTreeMap<usize, Block>) {
+        for key in entry.keys() {
+            let span = match entry.value(&key) {
+                Some(value) => value.span(),
+                None => continue,
+            };
+            // `*` items start on the line after the key
+            let mut first = span.start().line();
+            if first > 1 && self.line(first).trim_start().starts_with('*') {
+                first -= 1;
+            }
+            let mut end = span.end().line();
+            while end < self.lines.len() && self.line(end + 1).trim_start().starts_with('>') {
+                end += 1;
+            }
+            let block = blocks.entry(first).or_insert(Block { end, nodes: Vec::new() });
+            block.end = block.end.max(end);
+            block.nodes.push((path.clone(), key));
+        }
+        for (i, subentry) in entry.subentries().iter().enumerate() {
+            path.push(i);
+            self.index_blocks(subentry, path, blocks);
+            path.pop();
+        }
+    }
+
+    // the line holding byte `offset`, from 1
+    fn line_of(&self, offset: usize) -> usize {
+        self.lines.partition_point(|&start| start <= offset)
+    }
+
+    // the text of line `line`, from 1
+    fn line(&self, line: usize) -> &str {
+        let start = self.lines[line - 1];
+        match self.lines.get(line) {
+            Some(next) => &self.source[start..next - 1],
+            None => &self.source[start..],
+        }
+    }
+
+    // Lines starting within the edited range are replaced by those of the text,
+    // and the following lines move by the change in length.
+    fn update_lines(&mut self, edit: &Edit) {
+        let range = &edit.range;
+        let from = self.lines.partition_point(|&start| start <= range.start);
+        let to = self.lines.partition_point(|&start| start <= range.end);
+        let inserted: Vec<usize> = edit.text.match_indices('\n').map(|(i, _)| range.start + i + 1).collect();
+        let moved = from + inserted.len();
+        self.lines.splice(from..to, inserted);
+        for start in &mut self.lines[moved..] {
+            *start = *start + edit.text.len() - range.len();
+        }
+    }
+}
+
+// The lines of a variable, from line `line` of the document, parsed alone:
+// its key and whether it is optional and typed, its value, and whether it is a reference.
+// Declarations for the whole dataset on the root entry, such as `@ MISSING(...)`, come first.
+fn parse_variable(text: &str, line: usize, root: &Entry, options: &ParserOptions) -> Option<((String, bool, bool), Value, bool)> {
+    let mut source = String::new();
+    for decorator in root.dataset_decorators() {
+        source.push_str(&format!("@ {}\n", decorator));
+    }
+    let first_line = line.checked_sub(source.lines().count() + 1).filter(|l| *l >= 1)?;
+    source.push_str("# _\n");
+    source.push_str(text);
+
+    let entry = Parser::with_options(&source, options.clone()).starting_at(first_line).parse_tree().ok()??;
+    let keys = entry.keys();
+    if keys.len() != 1 || !entry.subentries().is_empty() || entry.annotation().is_some() {
+        return None;
+    }
+    let key = keys[0].clone();
+    let value = entry.value(&key)?.clone();
+    let is_ref = entry.is_ref_key(&key);
+    Some(((key.clone(), entry.is_optional(&key), entry.is_typed(&key)), value, is_ref))
+}
+
+// Check the values of the variables at `nodes` against the constraints on them,
+// declared on their entry or its ancestors.
+fn validate_nodes(root: &Entry, nodes: &[Node]) -> Result<(), ReamError> {
+    for (path, key) in nodes {
+        let mut entry = root;
+        let mut constraints = declared(entry, &[]);
+        for i in path {
+            entry = &entry.subentries()[*i];
+            constraints = declared(entry, &constraints);
+        }
+        constraints.retain(|(_, k, _)| k == key);
+        check(entry, &constraints)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn edit(source: &str, from: &str, to: &str) -> Edit {
+        let start = source.find(from).unwrap();
+        Edit { range: start..start + from.len(), text: to.to_string() }
+    }
+
+    fn full_parse(source: &str) -> Entry {
+        Parser::new(source).parse_entry().unwrap().unwrap()
+    }
+
+    #[test]
+    fn patch_value() {
+        let text = "# Country\n- name: Belgium\n- population: 11\n\n## Language\n- name: Dutch\n  > official\n- country (ref): Country$name\n- share: 0.6";
+        let mut document = Document::new(text);
+
+        let e = edit(text, "Belgium", "Belgique");
+        assert!(document.patch(&e));
+        let mut document = Document::new(text);
+        let entry = document.edit(&e).unwrap().unwrap().clone();
+        assert_eq!(entry, full_parse(document.source()));
+        assert_eq!(entry.to_csv_str().unwrap(), "Belgique,11,Dutch,Belgique,0.6\n");
+
+        // the annotation on the next line is kept
+        let source = document.source().to_string();
+        let e = edit(&source, "Dutch", "Flemish");
+        assert!(document.patch(&e));
+        let mut document = Document::new(&source);
+        let entry = document.edit(&e).unwrap().unwrap().clone();
+        assert_eq!(entry, full_parse(document.source()));
+    }
+
+    #[test]
+    fn fall_back_to_full_parse() {
+        let text = "# Country\n- name: Belgium\n- population: 11\n\n## Language\n- name: Dutch";
+        let edits = [
+            edit(text, "11", "12\n- area: 30"), // new line
+            edit(text, "# Country", "# State"), // header
+            edit(text, "- name: Dutch", "- label: Dutch"), // key
+        ];
+        for e in &edits {
+            let mut document = Document::new(text);
+            assert!(!document.patch(e));
+            let mut document = Document::new(text);
+            let entry = document.edit(e).unwrap().unwrap().clone();
+            assert_eq!(entry, full_parse(document.source()));
+        }
+
+        // a change of type is checked against the schema by a full parse
+        let text = "# World\n\n## Country\n- population: 11\n\n## Country\n- population: 67";
+        let mut document = Document::new(text);
+        assert!(matches!(
+            document.edit(&edit(text, "67", "many")),
+            Err(ReamError::SchemaError(SchemaErrorType::IncompatibleTypes(_)))
+        ));
+        let entry = document.edit(&edit(document.source(), "many", "68")).unwrap().unwrap().clone();
+        assert_eq!(entry, full_parse(document.source()));
+    }
+
+    #[test]
+    fn patch_missing_code() {
+        let text = "@ MISSING(refused)\n# Person\n- name: Ann\n- note: none";
+        let e = edit(text, "none", "NA(refused)");
+        let mut document = Document::new(text);
+        let entry = document.edit(&e).unwrap().unwrap().clone();
+        assert_eq!(entry, full_parse(document.source()));
+        assert!(entry.value(&"note".to_string()).unwrap().is_na());
+    }
+
+    // whether `e` patched the tree, which must equal a full parse of the edited source
+    fn patched(source: &str, e: &Edit) -> bool {
+        let patched = Document::new(source).patch(e);
+        let mut document = Document::new(source);
+        let entry = document.edit(e).unwrap().unwrap().clone();
+        assert_eq!(entry, full_parse(document.source()));
+        let lines = Document::new(document.source()).lines;
+        assert_eq!(document.lines, lines);
+        patched
+    }
+
+    #[test]
+    fn patch_resolves_dependents() {
+        let text = "# Country\n- name: Belgium\n- total = sum(Region$population)\n\n## Region\n- name: Flanders\n- population: 6.8\n- country (ref): Country$name\n\n## Region\n- name: Wallonia\n- population: 3.7\n- country (ref): Country$name";
+        assert!(patched(text, &edit(text, "6.8", "6.9")));
+        assert!(patched(text, &edit(text, "Belgium", "Belgique")));
+
+        let mut document = Document::new(text);
+        let entry = document.edit(&edit(text, "3.7", "3.6")).unwrap().unwrap();
+        assert_eq!(entry.value(&"total".to_string()).unwrap().get_raw(), "10.4");
+
+        // references are resolved again as a whole
+        assert!(patched(text, &edit(text, "sum(Region$population)", "max(Region$population)")));
+    }
+
+    #[test]
+    fn patch_variable_lines() {
+        let text = "@ LEVELS(status, member, observer)\n# Country\n- status (cat status): member\n- languages (list str):\n  * Dutch\n  * French\n  > official\n- area: 30";
+        // declarations of the dataset are known to the variable alone
+        assert!(patched(text, &edit(text, ": member", ": observer")));
+        // items and annotations, keeping the number of lines
+        assert!(patched(text, &edit(text, "Dutch\n  * French", "Dutch\n  * German")));
+        assert!(patched(text, &edit(text, "official", "official languages")));
+        // new lines move spans below
+        assert!(patched(text, &edit(text, "French", "French\n  * German")));
+        assert!(patched(text, &edit(text, "  > official\n", "")));
+        assert!(patched(text, &edit(text, "  * French\n", "")));
+    }
+
+    #[test]
+    fn patch_moves_lines_below() {
+        let text = "# Country\n- languages (list str):\n  * Dutch\n- name: Belgium\n\n## Region\n- name: Flanders\n- country (ref str): Country$name\n\n## Region\n- name: Wallonia\n- country (ref str): Country$name";
+        assert!(patched(text, &edit(text, "Dutch", "Dutch\n  * French\n  * German")));
+        assert!(patched(text, &edit(text, "Flanders", "Flanders\n  > Dutch-speaking")));
+
+        // later edits patch the moved tree
+        let mut document = Document::new(text);
+        document.edit(&edit(text, "Dutch", "Dutch\n  * French")).unwrap();
+        let source = document.source().to_string();
+        let e = edit(&source, "Wallonia", "Wallonie");
+        assert!(document.patch(&e));
+        let entry = document.edit(&e).unwrap().unwrap().clone();
+        assert_eq!(entry, full_parse(document.source()));
+        let country = entry.entry_at(&[1]).unwrap().value(&"country".to_string()).unwrap();
+        assert_eq!(country.span().start().line(), 13);
+        assert_eq!(country.origin().unwrap().line, 5);
+    }
+
+    #[test]
+    fn patch_checks_constraints() {
+        let text = "@ CHECK(Region$population, population > 0)\n# Country\n\n## Region\n- population: 6.8";
+        let mut document = Document::new(text);
+        assert!(matches!(document.edit(&edit(text, "6.8", "-1")), Err(ReamError::ConstraintError(_))));
+        let entry = document.edit(&edit(document.source(), "-1", "7")).unwrap().unwrap().clone();
+        assert_eq!(entry, full_parse(document.source()));
+    }
+}
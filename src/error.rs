@@ -1,4 +1,8 @@
+use crate::format::ValueType;
+use crate::scanner::{Marker, Span};
+
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ReamError {
@@ -8,6 +12,9 @@ pub enum ReamError {
     ReferenceError(ReferenceErrorType),
     SchemaError(SchemaErrorType),
     DecoratorError(DecoratorErrorType),
+    ExportError(ExportErrorType),
+    ExpressionError(ExpressionErrorType),
+    ConstraintError(Box<ConstraintViolation>),
     DuplicateKeys, // TODO: better error classification
     Placeholder,
 }
@@ -18,24 +25,147 @@ pub enum SchemaErrorType {
     IncorrectKeys,
     IncorrectClass,
     IncorrectSchema, // TODO: need to be more specific
+    IncorrectIdKey,
+    IncorrectForeignKey,
+    IncompatibleTypes(TypeConflict),
+    MixedUnits(Box<UnitConflict>),
+}
+
+// Two entries of the same class disagree on the type of a key.
+// `expected` comes from the entry that defined the schema.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TypeConflict {
+    pub class: String,
+    pub key: String,
+    pub expected: ValueType,
+    pub expected_at: Marker,
+    pub found: ValueType,
+    pub found_at: Marker,
+}
+
+// Two entries of the same class give a key in different units, or one without a unit.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitConflict {
+    pub class: String,
+    pub key: String,
+    pub expected: Option<String>,
+    pub expected_at: Marker,
+    pub found: Option<String>,
+    pub found_at: Marker,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ReferenceErrorType {
     ReferenceNotFound,
-    InvalidReference,
-    EntryClassNotFound,
-    VariableKeyNotFound,
-    IncompatibleTypes,
+    InvalidReference(ReferenceSite),
+    EntryClassNotFound(ReferenceSite),
+    VariableKeyNotFound(ReferenceSite),
+    IncompatibleTypes(Box<ReferenceTypeConflict>),
     DuplicateKeys,
+    CyclicReference(Vec<ReferenceSite>),
+    IdentifierNotFound(ReferenceSite),
+    DanglingForeignKey(ReferenceSite),
+    DuplicateIdentifier(IdentifierConflict),
+}
+
+// Two entries of the same class share an identifier.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdentifierConflict {
+    pub class: String,
+    pub id: String,
+    pub first: Marker,
+    pub second: Marker,
+}
+
+// A typed reference, such as `(ref num)`, resolved to a value of another type,
+// or an operand of an expression with the wrong type.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReferenceTypeConflict {
+    pub site: ReferenceSite,
+    pub expected: ValueType,
+    pub found: ValueType,
+}
+
+// A reference such as `Language$size` held by key `key` of an entry of `class`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceSite {
+    pub class: String,
+    pub key: String,
+    pub reference: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ReferenceSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{reference}` in key `{key}` of `{class}` at {span}",
+            reference = self.reference,
+            key = self.key,
+            class = self.class,
+            span = self.span,
+        )
+    }
+}
+
+// A value of a categorical type, such as `(cat regime)`, outside its levels.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LevelMismatch {
+    pub levels: String,
+    pub value: String,
+    pub nearest: Option<String>, // the most similar level
+    pub span: Span,
+}
+
+// A single value where a list is declared, such as the item `* 1` of `(list list int)`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListMismatch {
+    pub expected: ValueType,
+    pub value: String,
+    pub span: Span,
+}
+
+// A number in a unit that does not convert to the declared unit, such as `kg` for `km2`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitMismatch {
+    pub expected: String,
+    pub found: String,
+    pub span: Span,
+}
+
+// A value, or an item of a list, outside a constraint declared by `@ CHECK(...)`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConstraintViolation {
+    pub class: String,
+    pub key: String,
+    pub constraint: String,
+    pub value: String,
+    pub span: Span,
+}
+
+// A value rejected by a custom type of the `TypeRegistry`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomMismatch {
+    pub typ: String,
+    pub value: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum TypeErrorType {
     UnknownType,
     InvalidNumber,
+    InvalidInteger,
     InvalidBoolean,
+    InvalidDate,
     HeterogeneousList,
+    NotAList(Box<ListMismatch>),
+    UnknownMissingCode,
+    UnknownLevel(Box<LevelMismatch>),
+    UndeclaredLevels(String), // no `@ LEVELS(...)` for the name
+    UnknownUnit(String),
+    IncompatibleUnit(Box<UnitMismatch>),
+    InvalidCustomValue(Box<CustomMismatch>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -64,7 +194,156 @@ pub enum ScanErrorType {
     WrongHeaderLevel,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ExpressionErrorType {
+    InvalidExpression(ReferenceSite),
+    IncompatibleTypes(Box<ReferenceTypeConflict>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ExportErrorType {
+    TooManyMissingCodes,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum DecoratorErrorType {
     InvalidDecorator,
+    MisplacedDecorator,
+    TemplateNotFound(String),
+}
+
+impl fmt::Display for ReamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SchemaError(SchemaErrorType::IncompatibleTypes(c)) => write!(
+                f,
+                "SchemaError: key `{key}` of `{class}` is `{found}` in the entry at {found_at}, \
+                 but `{expected}` in the entry at {expected_at}",
+                key = c.key,
+                class = c.class,
+                found = c.found,
+                found_at = c.found_at,
+                expected = c.expected,
+                expected_at = c.expected_at,
+            ),
+            Self::SchemaError(SchemaErrorType::MixedUnits(c)) => write!(
+                f,
+                "SchemaError: key `{key}` of `{class}` is {found} in the entry at {found_at}, \
+                 but {expected} in the entry at {expected_at}",
+                key = c.key,
+                class = c.class,
+                found = describe_unit(&c.found),
+                found_at = c.found_at,
+                expected = describe_unit(&c.expected),
+                expected_at = c.expected_at,
+            ),
+            Self::ConstraintError(v) => write!(
+                f,
+                "ConstraintError: `{value}` in key `{key}` of `{class}` at {span} violates `{constraint}`",
+                value = v.value,
+                key = v.key,
+                class = v.class,
+                span = v.span,
+                constraint = v.constraint,
+            ),
+            Self::ReferenceError(ReferenceErrorType::InvalidReference(site)) => write!(
+                f,
+                "ReferenceError: invalid reference {}; expecting `Class$key`",
+                site,
+            ),
+            Self::ReferenceError(ReferenceErrorType::EntryClassNotFound(site)) => write!(
+                f,
+                "ReferenceError: no ancestor or descendant entry of the class referenced by {}",
+                site,
+            ),
+            Self::ReferenceError(ReferenceErrorType::VariableKeyNotFound(site)) => write!(
+                f,
+                "ReferenceError: referenced entry has no such key for {}",
+                site,
+            ),
+            Self::ReferenceError(ReferenceErrorType::IdentifierNotFound(site)) => write!(
+                f,
+                "ReferenceError: no entry with the identifier referenced by {}",
+                site,
+            ),
+            Self::ReferenceError(ReferenceErrorType::IncompatibleTypes(c)) => write!(
+                f,
+                "ReferenceError: {site} resolves to `{found}`, but `{expected}` is expected",
+                site = c.site,
+                found = c.found,
+                expected = c.expected,
+            ),
+            Self::DecoratorError(DecoratorErrorType::TemplateNotFound(name)) => write!(
+                f,
+                "DecoratorError: template `{}` is used before it is declared",
+                name,
+            ),
+            Self::ExpressionError(ExpressionErrorType::InvalidExpression(site)) => write!(
+                f,
+                "ExpressionError: invalid expression {}",
+                site,
+            ),
+            Self::ExpressionError(ExpressionErrorType::IncompatibleTypes(c)) => write!(
+                f,
+                "ExpressionError: {site} has an operand of type `{found}`, but `{expected}` is expected",
+                site = c.site,
+                found = c.found,
+                expected = c.expected,
+            ),
+            Self::ReferenceError(ReferenceErrorType::DanglingForeignKey(site)) => write!(
+                f,
+                "ReferenceError: dangling foreign key {}",
+                site,
+            ),
+            Self::ReferenceError(ReferenceErrorType::DuplicateIdentifier(c)) => write!(
+                f,
+                "ReferenceError: identifier `{id}` of `{class}` is used by the entries at {first} and {second}",
+                id = c.id,
+                class = c.class,
+                first = c.first,
+                second = c.second,
+            ),
+            Self::TypeError(TypeErrorType::UnknownLevel(m)) => {
+                write!(f, "TypeError: `{}` at {} is not a level of `{}`", m.value, m.span, m.levels)?;
+                match &m.nearest {
+                    Some(nearest) => write!(f, "; did you mean `{}`?", nearest),
+                    None => Ok(()),
+                }
+            }
+            Self::TypeError(TypeErrorType::UndeclaredLevels(name)) => write!(
+                f,
+                "TypeError: levels `{}` are not declared by `@ LEVELS(...)`",
+                name,
+            ),
+            Self::TypeError(TypeErrorType::UnknownUnit(unit)) => write!(f, "TypeError: unknown unit `{}`", unit),
+            Self::TypeError(TypeErrorType::IncompatibleUnit(m)) => write!(
+                f,
+                "TypeError: `{found}` at {span} does not convert to `{expected}`",
+                found = m.found,
+                span = m.span,
+                expected = m.expected,
+            ),
+            Self::TypeError(TypeErrorType::NotAList(m)) => {
+                write!(f, "TypeError: `{}` at {} is not a `{}`", m.value, m.span, m.expected)
+            }
+            Self::TypeError(TypeErrorType::InvalidCustomValue(m)) => {
+                write!(f, "TypeError: `{}` at {} is not a valid `{}`", m.value, m.span, m.typ)
+            }
+            Self::ReferenceError(ReferenceErrorType::CyclicReference(sites)) => {
+                write!(f, "ReferenceError: cyclic reference")?;
+                for site in sites {
+                    write!(f, "\n  {}", site)?;
+                }
+                Ok(())
+            }
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+fn describe_unit(unit: &Option<String>) -> String {
+    match unit {
+        Some(unit) => format!("in `{}`", unit),
+        None => "without a unit".to_string(),
+    }
 }
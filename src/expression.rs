@@ -0,0 +1,648 @@
+use crate::format::*;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// The value of a computed variable, e.g. `- density (num) = population / area`.
+// Operands are keys of the same entry, or references resolved like `(ref)` values,
+// and are evaluated by the resolver.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Expression {
+    source: String,
+    root: Expr,
+    operands: Vec<Operand>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub enum Expr {
+    Literal(ValueBase, ValueType),
+    Operand(usize), // index into `Expression::operands`
+    Unary(Operator, Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Operator {
+    Add, // also concatenates `str`
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    Not,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub enum Operand {
+    Key(String),          // key of the same entry
+    Ref(Reference),       // `Class$key`, `Class[id]$key` or `sum(Class$key)`
+}
+
+// An operand of the wrong type: the operator (or the declared type) expected `expected`.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub expected: ValueType,
+    pub found: ValueType,
+}
+
+// Why an expression has no value.
+#[derive(Debug)]
+pub enum EvalError {
+    Mismatch(Mismatch),
+    Overflow, // a result beyond the range of `f64`
+}
+
+impl From<Mismatch> for EvalError {
+    fn from(mismatch: Mismatch) -> Self {
+        Self::Mismatch(mismatch)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Token {
+    Num(String),
+    Str(String),
+    Name(String), // key, keyword or reference
+    Symbol(&'static str),
+    LeftParen,
+    RightParen,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Option<Self> {
+        let tokens = tokenize(source)?;
+        let mut parser = ExprParser {
+            tokens,
+            cursor: 0,
+            operands: Vec::new(),
+        };
+        let root = parser.parse_if()?;
+        if parser.cursor != parser.tokens.len() {
+            return None;
+        }
+        Some(Self {
+            source: source.trim().to_string(),
+            root,
+            operands: parser.operands,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn operands(&self) -> &[Operand] {
+        &self.operands
+    }
+
+    // `values` holds the value of each operand.
+    // Any missing operand makes the result missing.
+    pub fn evaluate(&self, values: &[(ValueBase, ValueType)]) -> Result<(ValueBase, ValueType), EvalError> {
+        evaluate(&self.root, values)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+// two-character symbols first
+const SYMBOLS: [&str; 10] = ["==", "!=", "<=", ">=", "+", "-", "*", "/", "<", ">"];
+
+fn tokenize(source: &str) -> Option<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        match c {
+            ' ' | '\t' => i += 1,
+            '"' => {
+                let end = (i + 1..chars.len()).find(|j| chars[*j] == '"')?;
+                tokens.push(Token::Str(chars[i + 1..end].iter().collect()));
+                i = end + 1;
+            }
+            '(' => {
+                tokens.push(Token::LeftParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RightParen);
+                i += 1;
+            }
+            _ if c.is_ascii_digit() || (c == '.' && matches!(chars.get(i + 1), Some(d) if d.is_ascii_digit())) => {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                tokens.push(Token::Num(chars[start..i].iter().collect()));
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                // `key`, `Class$key` or `Class[id]$key`
+                while i < chars.len() {
+                    match chars[i] {
+                        '[' => i = (i..chars.len()).find(|j| chars[*j] == ']')? + 1,
+                        d if d.is_alphanumeric() || d == '_' || d == '$' => i += 1,
+                        _ => break,
+                    }
+                }
+                tokens.push(Token::Name(chars[start..i].iter().collect()));
+            }
+            _ => {
+                let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+                let symbol: &'static str = SYMBOLS.iter().find(|s| rest.starts_with(**s))?;
+                tokens.push(Token::Symbol(symbol));
+                i += symbol.len();
+            }
+        }
+    }
+    Some(tokens)
+}
+
+// Recursive descent, from the loosest binding:
+// `if _ then _ else _`, `or`, `and`, `not`, comparisons, `+ -`, `* /`, unary `-`
+struct ExprParser {
+    tokens: Vec<Token>,
+    cursor: usize,
+    operands: Vec<Operand>,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor)
+    }
+
+    fn take(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.cursor).cloned();
+        self.cursor += 1;
+        token
+    }
+
+    fn is_name(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(n)) if n == name)
+    }
+
+    fn expect_name(&mut self, name: &str) -> Option<()> {
+        if !self.is_name(name) {
+            return None;
+        }
+        self.cursor += 1;
+        Some(())
+    }
+
+    fn parse_if(&mut self) -> Option<Expr> {
+        if !self.is_name("if") {
+            return self.parse_or();
+        }
+        self.cursor += 1;
+        let condition = self.parse_if()?;
+        self.expect_name("then")?;
+        let then = self.parse_if()?;
+        self.expect_name("else")?;
+        let otherwise = self.parse_if()?;
+        Some(Expr::If(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn parse_or(&mut self) -> Option<Expr> {
+        let mut left = self.parse_and()?;
+        while self.is_name("or") {
+            self.cursor += 1;
+            let right = self.parse_and()?;
+            left = Expr::Binary(Operator::Or, Box::new(left), Box::new(right));
+        }
+        Some(left)
+    }
+
+    fn parse_and(&mut self) -> Option<Expr> {
+        let mut left = self.parse_not()?;
+        while self.is_name("and") {
+            self.cursor += 1;
+            let right = self.parse_not()?;
+            left = Expr::Binary(Operator::And, Box::new(left), Box::new(right));
+        }
+        Some(left)
+    }
+
+    fn parse_not(&mut self) -> Option<Expr> {
+        if self.is_name("not") {
+            self.cursor += 1;
+            let operand = self.parse_not()?;
+            return Some(Expr::Unary(Operator::Not, Box::new(operand)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Option<Expr> {
+        let left = self.parse_sum()?;
+        let operator = match self.peek() {
+            Some(Token::Symbol("==")) => Operator::Eq,
+            Some(Token::Symbol("!=")) => Operator::Ne,
+            Some(Token::Symbol("<")) => Operator::Lt,
+            Some(Token::Symbol("<=")) => Operator::Le,
+            Some(Token::Symbol(">")) => Operator::Gt,
+            Some(Token::Symbol(">=")) => Operator::Ge,
+            _ => return Some(left),
+        };
+        self.cursor += 1;
+        let right = self.parse_sum()?;
+        Some(Expr::Binary(operator, Box::new(left), Box::new(right)))
+    }
+
+    fn parse_sum(&mut self) -> Option<Expr> {
+        let mut left = self.parse_product()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Symbol("+")) => Operator::Add,
+                Some(Token::Symbol("-")) => Operator::Sub,
+                _ => return Some(left),
+            };
+            self.cursor += 1;
+            let right = self.parse_product()?;
+            left = Expr::Binary(operator, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_product(&mut self) -> Option<Expr> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Symbol("*")) => Operator::Mul,
+                Some(Token::Symbol("/")) => Operator::Div,
+                _ => return Some(left),
+            };
+            self.cursor += 1;
+            let right = self.parse_unary()?;
+            left = Expr::Binary(operator, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Option<Expr> {
+        if self.peek() == Some(&Token::Symbol("-")) {
+            self.cursor += 1;
+            let operand = self.parse_unary()?;
+            return Some(Expr::Unary(Operator::Neg, Box::new(operand)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Option<Expr> {
+        match self.take()? {
+            Token::Num(n) if is_num(&n) => {
+                let typ = if is_int(&n) { ValueType::Int } else { ValueType::Num };
+                Some(Expr::Literal(ValueBase::Num(Number::parse(&n)?), typ))
+            }
+            Token::Str(s) => Some(Expr::Literal(ValueBase::Str(s), ValueType::Str)),
+            Token::LeftParen => {
+                let inner = self.parse_if()?;
+                match self.take()? {
+                    Token::RightParen => Some(inner),
+                    _ => None,
+                }
+            }
+            Token::Name(n) => self.parse_name(n),
+            _ => None,
+        }
+    }
+
+    fn parse_name(&mut self, name: String) -> Option<Expr> {
+        let operand = match name.as_str() {
+            "TRUE" | "FALSE" => return Some(Expr::Literal(ValueBase::Bool(name), ValueType::Bool)),
+            "NA" => return Some(Expr::Literal(ValueBase::Na(None), ValueType::Unknown)),
+            "if" | "then" | "else" | "and" | "or" | "not" => return None,
+            // aggregation, e.g. `sum(Class$key)`
+            _ if self.peek() == Some(&Token::LeftParen) => {
+                self.cursor += 1;
+                let inner = match self.take()? {
+                    Token::Name(inner) => inner,
+                    _ => return None,
+                };
+                if self.take()? != Token::RightParen {
+                    return None;
+                }
+                Operand::Ref(Reference::parse(&format!("{}({})", name, inner))?)
+            }
+            _ if name.contains('$') => Operand::Ref(Reference::parse(&name)?),
+            _ => Operand::Key(name),
+        };
+        let i = match self.operands.iter().position(|o| *o == operand) {
+            Some(i) => i,
+            None => {
+                self.operands.push(operand);
+                self.operands.len() - 1
+            }
+        };
+        Some(Expr::Operand(i))
+    }
+}
+
+fn evaluate(expr: &Expr, values: &[(ValueBase, ValueType)]) -> Result<(ValueBase, ValueType), EvalError> {
+    match expr {
+        Expr::Literal(value_base, typ) => Ok((value_base.clone(), typ.clone())),
+        Expr::Operand(i) => Ok(values[*i].clone()),
+        Expr::Unary(operator, operand) => {
+            let operand = evaluate(operand, values)?;
+            let expected = match operator {
+                Operator::Not => ValueType::Bool,
+                _ => ValueType::Num,
+            };
+            check(&expected, &operand.1)?;
+            let typ = match operand.1 {
+                ValueType::Int => ValueType::Int,
+                _ => expected,
+            };
+            let value_base = match &operand.0 {
+                ValueBase::Na(_) => ValueBase::Na(None),
+                v if *operator == Operator::Not => ValueBase::Bool(from_bool(v.get_raw() != "TRUE")),
+                ValueBase::Interval(i) => ValueBase::Interval(Box::new(Interval::new(
+                    Number::from_decimal(i.upper().decimal().neg()),
+                    Number::from_decimal(i.lower().decimal().neg()),
+                    i.point().map(|p| Number::from_decimal(p.decimal().neg())),
+                ))),
+                v => match to_decimal(v) {
+                    Some(d) => ValueBase::Num(Number::from_decimal(d.neg())),
+                    None => ValueBase::Na(None),
+                },
+            };
+            Ok((value_base, typ))
+        }
+        Expr::Binary(operator, left, right) => {
+            let left = evaluate(left, values)?;
+            let right = evaluate(right, values)?;
+            binary(*operator, left, right)
+        }
+        Expr::If(condition, then, otherwise) => {
+            let condition = evaluate(condition, values)?;
+            check(&ValueType::Bool, &condition.1)?;
+            let then = evaluate(then, values)?;
+            let otherwise = evaluate(otherwise, values)?;
+            let typ = check(&then.1, &otherwise.1)?;
+            let value_base = match condition.0 {
+                ValueBase::Na(_) => ValueBase::Na(None),
+                ValueBase::Bool(b) if b == "TRUE" => then.0,
+                _ => otherwise.0,
+            };
+            Ok((value_base, typ))
+        }
+    }
+}
+
+fn binary(
+    operator: Operator,
+    (left, left_typ): (ValueBase, ValueType),
+    (right, right_typ): (ValueBase, ValueType),
+) -> Result<(ValueBase, ValueType), EvalError> {
+    // both operands have the type expected by the operator
+    let is_str = left_typ == ValueType::Str || right_typ == ValueType::Str;
+    let is_date = left_typ.is_date() || right_typ.is_date();
+    let expected = match operator {
+        Operator::Eq | Operator::Ne | Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge if is_date => {
+            check(&left_typ, &right_typ)?
+        }
+        Operator::Add | Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge if is_str => ValueType::Str,
+        Operator::Eq | Operator::Ne => check(&left_typ, &right_typ)?,
+        Operator::And | Operator::Or => ValueType::Bool,
+        _ => ValueType::Num,
+    };
+    check(&expected, &left_typ)?;
+    check(&expected, &right_typ)?;
+
+    // integers stay integers, except by division
+    let integral = left_typ.merge(&right_typ) == Some(ValueType::Int);
+    let typ = match operator {
+        Operator::Add | Operator::Sub | Operator::Mul if integral => ValueType::Int,
+        Operator::Add | Operator::Sub | Operator::Mul | Operator::Div => expected.clone(),
+        _ => ValueType::Bool,
+    };
+    if matches!(left, ValueBase::Na(_)) || matches!(right, ValueBase::Na(_)) {
+        return Ok((ValueBase::Na(None), typ));
+    }
+
+    let numeric = expected.is_numeric();
+    let bounded = matches!(left, ValueBase::Interval(_)) || matches!(right, ValueBase::Interval(_));
+    if numeric && bounded {
+        let value_base = match left.bounds().zip(right.bounds()) {
+            Some((l, r)) => bounded_binary(operator, l, r)?,
+            None => ValueBase::Na(None),
+        };
+        return Ok((value_base, typ));
+    }
+    let decimals = to_decimal(&left).zip(to_decimal(&right));
+    let ordering = if numeric {
+        decimals.map(|(l, r)| l.cmp(&r))
+    } else if expected.is_date() {
+        // in UTC for datetimes
+        Some(date_key(&left.get_raw(), &expected).cmp(&date_key(&right.get_raw(), &expected)))
+    } else {
+        Some(left.get_raw().cmp(&right.get_raw()))
+    };
+    let value_base = match operator {
+        Operator::Add if !numeric => ValueBase::Str(left.get_raw() + &right.get_raw()),
+        // exact, except for quotients without a finite decimal expansion; results beyond the range of `f64` are errors
+        Operator::Add | Operator::Sub | Operator::Mul | Operator::Div => match decimals {
+            None => ValueBase::Na(None),
+            // division by zero is missing
+            Some((_, r)) if operator == Operator::Div && r.is_zero() => ValueBase::Na(None),
+            Some((l, r)) => {
+                let result = match operator {
+                    Operator::Add => l.add(&r),
+                    Operator::Sub => l.sub(&r),
+                    Operator::Mul => l.mul(&r),
+                    _ => l.div(&r),
+                };
+                match result {
+                    Some(n) => ValueBase::Num(Number::from_decimal(n)),
+                    None => return Err(EvalError::Overflow),
+                }
+            }
+        },
+        Operator::Eq => ValueBase::Bool(from_bool(ordering == Some(std::cmp::Ordering::Equal))),
+        Operator::Ne => ValueBase::Bool(from_bool(ordering != Some(std::cmp::Ordering::Equal))),
+        Operator::Lt => ValueBase::Bool(from_bool(ordering == Some(std::cmp::Ordering::Less))),
+        Operator::Le => ValueBase::Bool(from_bool(ordering != Some(std::cmp::Ordering::Greater))),
+        Operator::Gt => ValueBase::Bool(from_bool(ordering == Some(std::cmp::Ordering::Greater))),
+        Operator::Ge => ValueBase::Bool(from_bool(ordering != Some(std::cmp::Ordering::Less))),
+        Operator::And => ValueBase::Bool(from_bool(left.get_raw() == "TRUE" && right.get_raw() == "TRUE")),
+        Operator::Or => ValueBase::Bool(from_bool(left.get_raw() == "TRUE" || right.get_raw() == "TRUE")),
+        Operator::Neg | Operator::Not => unreachable!(),
+    };
+    Ok((value_base, typ))
+}
+
+// intervals operate on their bounds, as aggregations do, and keep a point only if both operands have one;
+// a comparison is `NA` unless it holds, or fails, for every pair of numbers within the bounds
+fn bounded_binary(
+    operator: Operator,
+    (l1, u1, p1): (Decimal, Decimal, Option<Decimal>),
+    (l2, u2, p2): (Decimal, Decimal, Option<Decimal>),
+) -> Result<ValueBase, EvalError> {
+    let (always, never) = match operator {
+        Operator::Lt => (u1 < l2, l1 >= u2),
+        Operator::Le => (u1 <= l2, l1 > u2),
+        Operator::Gt => (l1 > u2, u1 <= l2),
+        Operator::Ge => (l1 >= u2, u1 < l2),
+        Operator::Eq => (l1 == u1 && u1 == l2 && l2 == u2, u1 < l2 || u2 < l1),
+        Operator::Ne => (u1 < l2 || u2 < l1, l1 == u1 && u1 == l2 && l2 == u2),
+        // a divisor that may be zero is missing
+        Operator::Div if l2 <= Decimal::zero() && u2 >= Decimal::zero() => return Ok(ValueBase::Na(None)),
+        _ => {
+            let apply = |a: Decimal, b: Decimal| {
+                let result = match operator {
+                    Operator::Add => a.add(&b),
+                    Operator::Sub => a.sub(&b),
+                    Operator::Mul => a.mul(&b),
+                    _ => a.div(&b),
+                };
+                result.ok_or(EvalError::Overflow)
+            };
+            // the extremes are among the results of the bounds
+            let results = [apply(l1, l2)?, apply(l1, u2)?, apply(u1, l2)?, apply(u1, u2)?];
+            let point = match p1.zip(p2) {
+                Some((a, b)) => Some(Number::from_decimal(apply(a, b)?)),
+                None => None,
+            };
+            return Ok(ValueBase::Interval(Box::new(Interval::new(
+                Number::from_decimal(*results.iter().min().unwrap()),
+                Number::from_decimal(*results.iter().max().unwrap()),
+                point,
+            ))));
+        }
+    };
+    Ok(match (always, never) {
+        (true, _) => ValueBase::Bool(from_bool(true)),
+        (_, true) => ValueBase::Bool(from_bool(false)),
+        _ => ValueBase::Na(None),
+    })
+}
+
+fn check(expected: &ValueType, found: &ValueType) -> Result<ValueType, Mismatch> {
+    match expected.merge(found) {
+        Some(typ) => Ok(typ),
+        None => Err(Mismatch {
+            expected: expected.clone(),
+            found: found.clone(),
+        }),
+    }
+}
+
+fn to_decimal(value_base: &ValueBase) -> Option<Decimal> {
+    match value_base {
+        ValueBase::Num(n) => Some(n.decimal()),
+        _ => None,
+    }
+}
+
+fn from_bool(b: bool) -> String {
+    if b { "TRUE" } else { "FALSE" }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn eval(source: &str, values: &[(ValueBase, ValueType)]) -> Result<(ValueBase, ValueType), EvalError> {
+        Expression::parse(source).unwrap().evaluate(values)
+    }
+
+    #[test]
+    fn precedence() {
+        let (value_base, typ) = eval("1 + 2 * 3 - -4 / 2", &[]).unwrap();
+        assert_eq!(value_base, ValueBase::Num(Number::parse("9").unwrap()));
+        assert_eq!(typ, ValueType::Num);
+    }
+
+    #[test]
+    fn operands() {
+        let expression = Expression::parse("population / area > 100 and Country$eu").unwrap();
+        assert_eq!(expression.operands().len(), 3);
+        assert_eq!(expression.operands()[0], Operand::Key("population".to_string()));
+        let values = vec![
+            (ValueBase::Num(Number::parse("1000").unwrap()), ValueType::Num),
+            (ValueBase::Num(Number::parse("5").unwrap()), ValueType::Num),
+            (ValueBase::Bool("TRUE".to_string()), ValueType::Bool),
+        ];
+        let (value_base, _) = expression.evaluate(&values).unwrap();
+        assert_eq!(value_base, ValueBase::Bool("TRUE".to_string()));
+    }
+
+    #[test]
+    fn concatenation_and_conditional() {
+        let values = vec![(ValueBase::Str("Belgium".to_string()), ValueType::Str)];
+        let (value_base, _) = eval("if name == \"Belgium\" then name + \" (BE)\" else name", &values).unwrap();
+        assert_eq!(value_base, ValueBase::Str("Belgium (BE)".to_string()));
+    }
+
+    #[test]
+    fn missing_operand() {
+        let values = vec![(ValueBase::Na(None), ValueType::Num)];
+        let (value_base, typ) = eval("x * 2", &values).unwrap();
+        assert_eq!(value_base, ValueBase::Na(None));
+        assert_eq!(typ, ValueType::Num);
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let values = vec![(ValueBase::Str("Belgium".to_string()), ValueType::Str)];
+        match eval("name * 2", &values) {
+            Err(EvalError::Mismatch(mismatch)) => {
+                assert_eq!(mismatch.expected, ValueType::Num);
+                assert_eq!(mismatch.found, ValueType::Str);
+            }
+            r => panic!("expected mismatch, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let number = |n: &str| (ValueBase::Num(Number::parse(n).unwrap()), ValueType::Num);
+        let largest = format!("{}e270", "9".repeat(38));
+        let values = vec![number(&largest), number("1e-300")];
+        for source in ["x + x", "-x - x", "x * x", "x / y"] {
+            assert!(matches!(eval(source, &values), Err(EvalError::Overflow)), "{}", source);
+        }
+        // rounded beyond 38 digits, within the range of `f64`
+        match eval("x + 1", &[number("1e-300")]).unwrap() {
+            (ValueBase::Num(n), _) => assert_eq!(n.decimal(), Decimal::parse("1").unwrap()),
+            r => panic!("expected a number, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn intervals() {
+        let interval = |i: &str| (ValueBase::Interval(Box::new(Interval::parse(i).unwrap())), ValueType::Num);
+        let raw = |source: &str, values: &[&str]| {
+            let values: Vec<(ValueBase, ValueType)> = values.iter().map(|v| interval(v)).collect();
+            eval(source, &values).unwrap().0.get_raw()
+        };
+        assert_eq!(raw("x * 2", &["2..3"]), "4..6");
+        assert_eq!(raw("-x", &["2..3"]), "-3..-2");
+        assert_eq!(raw("x - y", &["2..3", "-1..4"]), "-2..4");
+        assert_eq!(raw("x * y", &["2..3", "-1..4"]), "-3..12");
+        assert_eq!(raw("x + 1", &["2.5 ± 0.5"]), "3.5 ± 0.5");
+        assert_eq!(raw("x / y", &["2..3", "-1..4"]), "NA");
+
+        // comparisons hold for every number within the bounds, or are missing
+        assert_eq!(raw("x > 1", &["2..3"]), "TRUE");
+        assert_eq!(raw("x >= 3", &["2..3"]), "NA");
+        assert_eq!(raw("x > 3", &["2..3"]), "FALSE");
+        assert_eq!(raw("x == 5", &["2..3"]), "FALSE");
+        assert_eq!(raw("x == y", &["2..3", "2.5 ± 0.5"]), "NA");
+    }
+
+    #[test]
+    fn invalid_expression() {
+        assert!(Expression::parse("1 +").is_none());
+        assert!(Expression::parse("(1 + 2").is_none());
+        assert!(Expression::parse("if TRUE then 1").is_none());
+    }
+}
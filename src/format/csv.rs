@@ -1,10 +1,216 @@
 use crate::format::{*};
 
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub missing: String, // token for missing values
+    pub missing_style: MissingStyle,
+    pub join: bool,      // append the values of entries referenced by foreign keys
+    pub annotations: bool, // append the annotation of each entry
+    pub categories: CategoryStyle,
+    pub intervals: IntervalStyle,
+}
+
+// How keys holding intervals (`2..3`, `2.5 ± 0.5`) are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntervalStyle {
+    Bounds, // three columns: lower bound, upper bound and point
+    Raw,    // one column, as written
+}
+
+// How values of categorical types (`(cat name)`) are written.
+// Codes are numbered from 1 in the order declared by `@ LEVELS(...)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CategoryStyle {
+    Label, // the level itself
+    Code,  // its code
+}
+
+// How coded missing values (`NA(code)`) are written.
+// Codes are numbered in the order declared by `@ MISSING(...)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingStyle {
+    Token, // `missing` for every missing value
+    Label, // the code itself
+    Stata, // extended missing values `.a` to `.z`; `.` if uncoded
+    Spss,  // user-missing values `-1`, `-2`, ...
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            missing: "NA".to_string(),
+            missing_style: MissingStyle::Token,
+            join: false,
+            annotations: false,
+            categories: CategoryStyle::Label,
+            intervals: IntervalStyle::Bounds,
+        }
+    }
+}
+
+impl CsvOptions {
+    pub fn missing_value(&self, code: Option<&String>, codes: &[String]) -> String {
+        let index = code.and_then(|c| codes.iter().position(|d| d == c));
+        match (self.missing_style, index) {
+            (MissingStyle::Stata, None) => ".".to_string(),
+            (MissingStyle::Stata, Some(i)) => format!(".{}", (b'a' + i as u8) as char),
+            (MissingStyle::Spss, Some(i)) => format!("-{}", i + 1),
+            (MissingStyle::Label, Some(i)) => codes[i].clone(),
+            _ => self.missing.clone(),
+        }
+    }
+}
+
+// Dataset-wide information for writing rows, collected from the root entry.
+pub struct CsvContext<'a> {
+    codes: Vec<String>,
+    levels: HashMap<String, Vec<String>>,      // categorical type -> levels
+    ids: HashMap<(String, String), &'a Entry>, // (class, id) -> entry
+    widths: HashMap<String, usize>,            // class -> number of keys
+    recursive: HashSet<String>,                // classes nested in themselves
+    intervals: HashSet<(String, String)>,      // (class, key) holding intervals
+}
+
+impl<'a> CsvContext<'a> {
+    pub fn new(root: &'a Entry) -> Self {
+        let mut context = Self {
+            codes: root.missing_codes(),
+            levels: root.levels().into_iter().collect(),
+            ids: HashMap::new(),
+            widths: HashMap::new(),
+            recursive: HashSet::new(),
+            intervals: HashSet::new(),
+        };
+        context.index(root);
+        context
+    }
+
+    fn index(&mut self, entry: &'a Entry) {
+        if let Some(id) = entry.id() {
+            self.ids.insert((entry.class(), id.clone()), entry);
+        }
+        self.widths.insert(entry.class(), entry.keys.len());
+        for key in &entry.keys {
+            if entry.value(key).and_then(|v| v.as_interval()).is_some() {
+                self.intervals.insert((entry.class(), key.clone()));
+            }
+        }
+        for subentry in &entry.subentries {
+            if subentry.class == entry.class {
+                self.recursive.insert(entry.class());
+            }
+            self.index(subentry);
+        }
+    }
+
+    // a value, with levels of categorical types written as their codes
+    fn code(&self, value: &Value) -> String {
+        let name = match value.typ() {
+            ValueType::Cat(name) => name,
+            ValueType::List(t) => match &**t {
+                ValueType::Cat(name) => name,
+                _ => return value.get_raw(),
+            },
+            _ => return value.get_raw(),
+        };
+        let code = |level: &Value| match self.levels.get(name).and_then(|ls| ls.iter().position(|l| *l == level.get_raw())) {
+            Some(i) => (i + 1).to_string(),
+            None => level.get_raw(),
+        };
+        match &value.value {
+            ValueBase::List(list) => list.items.iter().map(code).collect::<Vec<String>>().join(";"),
+            _ => code(value),
+        }
+    }
+
+    // values of the entries referenced by a foreign key, joined by `;` for lists of foreign keys;
+    // their own foreign keys are not joined again, which could loop
+    fn join(&self, fk: &ForeignKey, value: &Value, options: &CsvOptions) -> Vec<String> {
+        let ids: Vec<String> = match &value.value {
+            ValueBase::List(list) => list.items.iter().map(|item| item.get_raw()).collect(),
+            ValueBase::Na(_) => Vec::new(),
+            _ => vec![value.get_raw()],
+        };
+        let mut width = self.widths.get(&fk.class).cloned().unwrap_or(0);
+        if options.intervals == IntervalStyle::Bounds {
+            width += 2 * self.intervals.iter().filter(|(class, _)| *class == fk.class).count();
+        }
+        let rows: Vec<Vec<String>> = ids
+            .iter()
+            .filter_map(|id| self.ids.get(&(fk.class.clone(), id.clone())))
+            .map(|entry| entry.values(options, self, false))
+            .collect();
+        if rows.is_empty() {
+            return vec![options.missing.clone(); width];
+        }
+        (0..width)
+            .map(|i| rows.iter().map(|row| row[i].clone()).collect::<Vec<String>>().join(";"))
+            .collect()
+    }
+}
+
 impl Entry {
 
-    // TODO: must exist a better way to write this >:(
+    pub fn get_variable_values(&self, options: &CsvOptions, context: &CsvContext) -> Vec<String> {
+        let mut output = self.values(options, context, options.join);
+        if options.annotations {
+            // one line per row
+            let annotation = self.annotation.as_deref().unwrap_or_default();
+            output.push(annotation.lines().collect::<Vec<_>>().join(" "));
+        }
+        output
+    }
+
+    // the values of the keys, followed by those of referenced entries if `join`
+    fn values(&self, options: &CsvOptions, context: &CsvContext, join: bool) -> Vec<String> {
+        let mut output: Vec<String> = Vec::new();
+        for key in self.keys.clone() {
+            let item = match self.value(&key) {
+                Some(item) => item,
+                None => continue,
+            };
+            let bounded = options.intervals == IntervalStyle::Bounds
+                && context.intervals.contains(&(self.class(), key.clone()));
+            if item.is_na() {
+                let missing = options.missing_value(item.missing_code(), &context.codes);
+                let width = if bounded { 3 } else { 1 };
+                output.extend(std::iter::repeat_n(missing, width));
+            } else if bounded {
+                // a number is its own bounds
+                match item.as_interval() {
+                    Some(interval) => {
+                        output.push(interval.lower().to_string());
+                        output.push(interval.upper().to_string());
+                        output.push(match interval.point() {
+                            Some(point) => point.to_string(),
+                            None => options.missing.clone(),
+                        });
+                    }
+                    None => output.extend(std::iter::repeat_n(item.get_raw(), 3)),
+                }
+            } else if options.categories == CategoryStyle::Code {
+                output.push(context.code(item));
+            } else {
+                output.push(item.get_raw());
+            }
+            if join {
+                if let Some(fk) = self.foreign_key(&key) {
+                    output.extend(context.join(fk, item, options));
+                }
+            }
+        }
+        output
+    }
+
     pub fn flatten_entry(&self) -> Vec<Vec<String>> {
-        let parent = self.get_variable_values();
+        self.flatten_entry_with(&CsvOptions::default(), &CsvContext::new(self))
+    }
+
+    // TODO: must exist a better way to write this >:(
+    pub fn flatten_entry_with(&self, options: &CsvOptions, context: &CsvContext) -> Vec<Vec<String>> {
+        let parent = self.get_variable_values(options, context);
         if self.subentries.is_empty() {
         // terminal node
             vec![parent]
@@ -12,8 +218,12 @@ impl Entry {
         // contains subentries
             let subentries = self.subentries.to_owned();
             let mut children: Vec<Vec<String>> = vec![];
-            for subentry in subentries {
-                let items = subentry.flatten_entry();
+            for (i, subentry) in subentries.iter().enumerate() {
+                let items = if context.recursive.contains(&subentry.class) && subentry.class != self.class {
+                    subentry.flatten_recursive(options, context, subentry.path_segment(&subentries, i), 1)
+                } else {
+                    subentry.flatten_entry_with(options, context)
+                };
                 for item in items {
                     children.push(item);
                 }
@@ -26,16 +236,71 @@ impl Entry {
         }
     }
 
+    // Entries of a class nested in itself have a row each,
+    // starting with their path and depth within the class.
+    fn flatten_recursive(&self, options: &CsvOptions, context: &CsvContext, path: String, depth: usize) -> Vec<Vec<String>> {
+        let own = [vec![path.clone(), depth.to_string()], self.get_variable_values(options, context)].concat();
+        let mut rows: Vec<Vec<String>> = vec![];
+        for subentry in self.subentries.iter().filter(|s| s.class != self.class) {
+            for item in subentry.flatten_entry_with(options, context) {
+                rows.push([own.to_owned(), item].concat());
+            }
+        }
+        if rows.is_empty() {
+            rows.push(own);
+        }
+        for (i, subentry) in self.subentries.iter().enumerate() {
+            if subentry.class == self.class {
+                let segment = subentry.path_segment(&self.subentries, i);
+                rows.extend(subentry.flatten_recursive(options, context, format!("{}/{}", path, segment), depth + 1));
+            }
+        }
+        rows
+    }
+
+    // the identifier, or the position among siblings of the class from 1
+    fn path_segment(&self, siblings: &[Entry], index: usize) -> String {
+        match self.id() {
+            Some(id) => id.clone(),
+            None => {
+                let position = siblings[..index].iter().filter(|s| s.class == self.class).count() + 1;
+                position.to_string()
+            }
+        }
+    }
+
     pub fn to_csv_list(&self) -> Result<Vec<Vec<String>>, ReamError> {
-        let rows = self.flatten_entry();
+        self.to_csv_list_with(&CsvOptions::default())
+    }
+
+    pub fn to_csv_list_with(&self, options: &CsvOptions) -> Result<Vec<Vec<String>>, ReamError> {
+        let context = CsvContext::new(self);
+        // Stata has 26 extended missing values
+        if options.missing_style == MissingStyle::Stata && context.codes.len() > 26 {
+            return Err(ReamError::ExportError(ExportErrorType::TooManyMissingCodes));
+        }
+        let rows = self.flatten_entry_with(options, &context);
         Ok(rows)
     }
 
     pub fn to_csv_str(&self) -> Result<String, ReamError> {
-        let rows = self.flatten_entry();
-        let raw = rows
-            .iter()
-            .fold(String::new(), |acc, row| acc + &row.join(",") + "\n");
+        self.to_csv_str_with(&CsvOptions::default())
+    }
+
+    pub fn to_csv_str_with(&self, options: &CsvOptions) -> Result<String, ReamError> {
+        let rows = self.to_csv_list_with(options)?;
+        let raw = rows.iter().fold(String::new(), |acc, row| {
+            acc + &row.iter().map(|field| quote(field)).collect::<Vec<String>>().join(",") + "\n"
+        });
         Ok(raw)
     }
 }
+
+// a field in double quotes, with inner quotes doubled,
+// if it holds a comma, a quote or a line break (RFC 4180)
+fn quote(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
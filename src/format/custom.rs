@@ -0,0 +1,53 @@
+use crate::format::ValueType;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+// A value type defined outside ream, such as ISO country codes or coordinates,
+// declared by its name like a built-in type, e.g. `- code (iso3): BEL`.
+pub trait ReamType: Send + Sync {
+    // the name in declarations; built-in types take precedence
+    fn name(&self) -> &str;
+
+    fn validate(&self, value: &str) -> bool;
+
+    // the form values are stored and written in, e.g. `BEL` for `bel`
+    fn canonicalize(&self, value: &str) -> String {
+        value.to_string()
+    }
+
+    // the built-in type values are stored and exported as, e.g. `num` for a latitude
+    fn export_type(&self) -> ValueType {
+        ValueType::Str
+    }
+}
+
+// Custom types known to the scanner and parser, set by `ParserOptions::types`.
+#[derive(Clone, Default)]
+pub struct TypeRegistry {
+    types: HashMap<String, Arc<dyn ReamType>>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // replaces any type of the same name
+    pub fn register(&mut self, typ: impl ReamType + 'static) {
+        self.types.insert(typ.name().to_string(), Arc::new(typ));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn ReamType> {
+        self.types.get(name).map(|typ| &**typ)
+    }
+}
+
+impl fmt::Debug for TypeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.types.keys().collect();
+        names.sort();
+        f.debug_tuple("TypeRegistry").field(&names).finish()
+    }
+}
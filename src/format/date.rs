@@ -0,0 +1,123 @@
+use crate::format::ValueType;
+
+// ISO 8601 dates, at the precision of their type:
+// `1999` (year), `1999-01` (yearmonth), `1999-01-31` (date)
+// and `1999-01-31T12:30`, with optional seconds, fraction and offset (datetime).
+pub fn is_date(value: &str, typ: &ValueType) -> bool {
+    date_key(value, typ).is_some()
+}
+
+// Seconds since 1970-01-01 and nanoseconds, ordering dates of the same type.
+// Datetimes with an offset are compared in UTC.
+pub fn date_key(value: &str, typ: &ValueType) -> Option<(i64, u32)> {
+    let (date, time) = match typ {
+        ValueType::Year => (parse_date(value, 1)?, None),
+        ValueType::YearMonth => (parse_date(value, 2)?, None),
+        ValueType::Date => (parse_date(value, 3)?, None),
+        ValueType::DateTime => {
+            let (date, time) = value.split_once('T')?;
+            (parse_date(date, 3)?, Some(parse_time(time)?))
+        }
+        _ => return None,
+    };
+    let (year, month, day) = date;
+    let days = days_from_civil(year, month, day);
+    let (seconds, nanos) = time.unwrap_or((0, 0));
+    Some((days * 86400 + seconds, nanos))
+}
+
+// `YYYY`, `YYYY-MM` or `YYYY-MM-DD`, with `parts` parts
+fn parse_date(value: &str, parts: usize) -> Option<(i64, u32, u32)> {
+    let fields: Vec<&str> = value.split('-').collect();
+    if fields.len() != parts {
+        return None;
+    }
+    let year = digits(fields[0], 4)? as i64;
+    let month = match fields.get(1) {
+        Some(m) => digits(m, 2)?,
+        None => 1,
+    };
+    if !(1..=12).contains(&month) {
+        return None;
+    }
+    let day = match fields.get(2) {
+        Some(d) => digits(d, 2)?,
+        None => 1,
+    };
+    if day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some((year, month, day))
+}
+
+// `HH:MM`, `HH:MM:SS` or `HH:MM:SS.f`, then `Z` or `+HH:MM` if any;
+// seconds from midnight in UTC and nanoseconds
+fn parse_time(value: &str) -> Option<(i64, u32)> {
+    let (time, offset) = if let Some(time) = value.strip_suffix('Z') {
+        (time, 0)
+    } else if let Some(i) = value.rfind(['+', '-']) {
+        let (time, offset) = value.split_at(i);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let (hours, minutes) = (digits(hours, 2)?, digits(minutes, 2)?);
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        (time, sign * (hours as i64 * 3600 + minutes as i64 * 60))
+    } else {
+        (value, 0)
+    };
+
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => (time, Some(fraction)),
+        None => (time, None),
+    };
+    let fields: Vec<&str> = time.split(':').collect();
+    if fields.len() < 2 || fields.len() > 3 || (fraction.is_some() && fields.len() != 3) {
+        return None;
+    }
+    let hours = digits(fields[0], 2)?;
+    let minutes = digits(fields[1], 2)?;
+    let seconds = match fields.get(2) {
+        Some(s) => digits(s, 2)?,
+        None => 0,
+    };
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    let nanos = match fraction {
+        Some(f) if f.is_empty() || f.len() > 9 => return None,
+        Some(f) => digits(f, f.len())? * 10u32.pow(9 - f.len() as u32),
+        None => 0,
+    };
+    let seconds = hours as i64 * 3600 + minutes as i64 * 60 + seconds as i64 - offset;
+    Some((seconds, nanos))
+}
+
+// exactly `n` ASCII digits
+fn digits(value: &str, n: usize) -> Option<u32> {
+    if value.len() != n || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
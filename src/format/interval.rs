@@ -0,0 +1,97 @@
+use crate::format::{is_int, is_num, Decimal, Number};
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// A range, `2..3`, or an estimate with its error, `2.5 ± 0.5` (or `2.5 +- 0.5`).
+// Estimates keep their point; ranges have none.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interval {
+    lower: Number,
+    upper: Number,
+    point: Option<Number>,
+}
+
+impl Interval {
+    pub fn new(lower: Number, upper: Number, point: Option<Number>) -> Self {
+        Self { lower, upper, point }
+    }
+
+    // `None` unless both numbers are valid and the lower bound is not above the upper bound
+    pub fn parse(value: &str) -> Option<Self> {
+        let estimate = value.split_once('±').or_else(|| value.split_once("+-"));
+        let interval = if let Some((point, error)) = estimate {
+            let (point, error) = (number(point)?, number(error)?);
+            if error.decimal() < Decimal::zero() {
+                return None;
+            }
+            Self {
+                lower: Number::from_decimal(point.decimal().sub(&error.decimal())?),
+                upper: Number::from_decimal(point.decimal().add(&error.decimal())?),
+                point: Some(point),
+            }
+        } else {
+            let (lower, upper) = value.split_once("..")?;
+            Self { lower: number(lower)?, upper: number(upper)?, point: None }
+        };
+        if interval.lower.decimal() > interval.upper.decimal() {
+            return None;
+        }
+        Some(interval)
+    }
+
+    pub fn lower(&self) -> &Number {
+        &self.lower
+    }
+
+    pub fn upper(&self) -> &Number {
+        &self.upper
+    }
+
+    pub fn point(&self) -> Option<&Number> {
+        self.point.as_ref()
+    }
+
+    // bounds and point are whole numbers
+    pub fn is_integral(&self) -> bool {
+        is_int(self.lower.lexeme())
+            && is_int(self.upper.lexeme())
+            && self.point.as_ref().is_none_or(|p| is_int(p.lexeme()))
+    }
+
+    // the interval with `f` applied to its bounds and point, e.g. to change units;
+    // `None` if `f` fails for any of them
+    pub fn map(&self, f: impl Fn(Decimal) -> Option<Decimal>) -> Option<Self> {
+        let point = match &self.point {
+            Some(p) => Some(Number::from_decimal(f(p.decimal())?)),
+            None => None,
+        };
+        Some(Self {
+            lower: Number::from_decimal(f(self.lower.decimal())?),
+            upper: Number::from_decimal(f(self.upper.decimal())?),
+            point,
+        })
+    }
+}
+
+fn number(value: &str) -> Option<Number> {
+    let value = value.trim();
+    match is_num(value) {
+        true => Number::parse(value),
+        false => None,
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // estimates with uneven bounds, e.g. from aggregations, are written as ranges
+        let estimate = self.point.as_ref().and_then(|point| {
+            let error = point.decimal().sub(&self.lower.decimal())?;
+            (Some(error) == self.upper.decimal().sub(&point.decimal())).then_some((point, error))
+        });
+        match estimate {
+            Some((point, error)) => write!(f, "{} ± {}", point, Number::from_decimal(error)),
+            None => write!(f, "{}..{}", self.lower, self.upper),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::format::{is_num, split_quantity, Interval};
+
+// Spellings of booleans and numbers accepted by the parser,
+// selected by `ParserOptions::literals` or `@ LITERALS(name)` on the root entry.
+// Values are read in canonical form: `TRUE`, `FALSE` and numbers such as `1234.5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiteralProfile {
+    pub true_words: Vec<String>,
+    pub false_words: Vec<String>,
+    pub decimal: char,
+    pub thousands: Option<char>,
+}
+
+impl Default for LiteralProfile {
+    fn default() -> Self {
+        Self::new(&["TRUE"], &["FALSE"], '.', None)
+    }
+}
+
+impl LiteralProfile {
+    pub fn new(true_words: &[&str], false_words: &[&str], decimal: char, thousands: Option<char>) -> Self {
+        Self {
+            true_words: true_words.iter().map(|w| w.to_string()).collect(),
+            false_words: false_words.iter().map(|w| w.to_string()).collect(),
+            decimal,
+            thousands,
+        }
+    }
+
+    // `default`, `en`, `de`, `fr` or `nl`
+    pub fn named(name: &str) -> Option<Self> {
+        let profile = match name {
+            "default" => Self::default(),
+            "en" => Self::new(
+                &["TRUE", "true", "True", "yes", "Yes"],
+                &["FALSE", "false", "False", "no", "No"],
+                '.',
+                Some(','),
+            ),
+            "de" => Self::new(
+                &["TRUE", "true", "wahr", "ja", "Ja"],
+                &["FALSE", "false", "falsch", "nein", "Nein"],
+                ',',
+                Some('.'),
+            ),
+            "fr" => Self::new(
+                &["TRUE", "true", "vrai", "oui", "Oui"],
+                &["FALSE", "false", "faux", "non", "Non"],
+                ',',
+                None,
+            ),
+            "nl" => Self::new(
+                &["TRUE", "true", "waar", "ja", "Ja"],
+                &["FALSE", "false", "onwaar", "nee", "Nee"],
+                ',',
+                Some('.'),
+            ),
+            _ => return None,
+        };
+        Some(profile)
+    }
+
+    // `value` in canonical form, if written otherwise;
+    // numbers are read word by word, e.g. `2,5 ± 0,5 million`,
+    // and kept as written unless the whole value is a number, interval or quantity
+    pub fn normalize(&self, value: &str) -> Option<String> {
+        if self.true_words.iter().any(|w| w == value) {
+            return Some("TRUE".to_string()).filter(|t| t != value);
+        }
+        if self.false_words.iter().any(|w| w == value) {
+            return Some("FALSE".to_string()).filter(|f| f != value);
+        }
+        let normal = value
+            .split(' ')
+            .map(|word| self.number(word).unwrap_or_else(|| word.to_string()))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let whole = is_num(&normal) || Interval::parse(&normal).is_some() || split_quantity(&normal).is_some();
+        Some(normal).filter(|n| whole && n != value)
+    }
+
+    // `1.234,5` as `1234.5`; `None` unless a number in the profile
+    fn number(&self, word: &str) -> Option<String> {
+        let (integer, fraction) = match word.split_once(self.decimal) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (word, None),
+        };
+        let integer = match self.thousands {
+            Some(sep) if integer.contains(sep) => {
+                // groups of three digits after the first
+                let groups: Vec<&str> = integer.split(sep).collect();
+                let digits = groups[0].trim_start_matches(['+', '-']);
+                if digits.is_empty() || digits.len() > 3 || groups[1..].iter().any(|g| g.len() != 3) {
+                    return None;
+                }
+                groups.concat()
+            }
+            _ => integer.to_string(),
+        };
+        let number = match fraction {
+            Some(fraction) => format!("{}.{}", integer, fraction),
+            None => integer,
+        };
+        match is_num(&number) {
+            true => Some(number),
+            false => None,
+        }
+    }
+}
@@ -1,9 +1,27 @@
 mod raw;
 mod csv;
 mod ast;
+mod custom;
+mod summary;
+mod date;
+mod interval;
+mod literal;
+mod number;
+mod template;
+mod unit;
+
+pub use self::csv::{CategoryStyle, CsvContext, CsvOptions, IntervalStyle, MissingStyle};
+pub use self::custom::{ReamType, TypeRegistry};
+pub use self::date::{date_key, is_date};
+pub use self::interval::Interval;
+pub use self::literal::LiteralProfile;
+pub use self::number::{Decimal, Number};
+pub use self::unit::{split_number, split_quantity, Unit};
 
 use crate::error::*;
 use crate::decorator::*;
+use crate::expression::Expression;
+use crate::scanner::{Marker, Span};
 
 use std::collections::HashMap;
 
@@ -14,13 +32,19 @@ pub struct Entry {
     class: String,
     level: usize,
     parent_class: Option<String>,
+    position: Marker,
+    id: Option<String>,
+    foreign_keys: Vec<ForeignKey>,
 
     decorators: Option<Vec<Decorator>>,
+    annotation: Option<String>, // `>` lines after the header
 
     variables: VariableMap,
     subentries: Vec<Entry>,
 
     keys: Vec<String>,
+    optional_keys: Vec<String>,
+    typed_keys: Vec<String>, // keys declared with a type, e.g. `(int)`
     ref_keys: Vec<String>,
 }
 
@@ -54,26 +78,146 @@ pub struct Value {
     value: ValueBase,
     annotation: Option<String>,
     typ: ValueType,
+    span: Span,
+    provenance: Provenance,
+    unit: Option<String>, // of numbers, e.g. `km2`
+    literal: Option<String>, // as written, if not in canonical form, e.g. `0,59`
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub enum ValueType {
     Str,
-    Num,
+    Num, // float
+    Int,
     Bool,
+    Date,      // `1999-01-31`
+    DateTime,  // `1999-01-31T12:30:00+01:00`
+    Year,      // `1999`
+    YearMonth, // `1999-01`
+    Cat(String), // one of the levels declared by `@ LEVELS(name, ...)`
     Unknown,
     List(Box<ValueType>),
-    Ref,
+    Ref(Box<ValueType>), // type of the resolved value; `Unknown` if untyped
+    Custom(String), // name of a type in the `TypeRegistry`
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub enum ValueBase {
     Str(String),
-    Num(String),
+    Num(Number),
+    Interval(Box<Interval>), // of `num` or `int`
     Bool(String),
+    Date(String), // ISO 8601, at the precision of its type
     Unknown(String),
     List(Box<List>),
-    Ref(String, String),
+    Ref(Reference),
+    Na(Option<String>), // missing value, with an optional code
+    Expr(Box<Expression>), // computed by the resolver
+}
+
+// Where a value comes from.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub enum Provenance {
+    Explicit,
+    Computed(String), // source of the expression
+    Inherited,        // default of the class, declared by `@ DEFAULT(...)`
+    Referenced(Origin), // resolved from a reference
+}
+
+// The value a reference resolves to, written as `Class$key@line`.
+// References to references point to the value at the end of the chain.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Origin {
+    pub class: String,
+    pub key: String,
+    pub line: usize,
+}
+
+// `key` holds identifiers of entries of `class`
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub key: String,
+    pub class: String,
+}
+
+// `Class$key`, or `Class[id]$key` for the entry with identifier `id`.
+// Aggregated over descendants with `sum(Class$key)`;
+// `count(Class)` is the only reference without a key.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Reference {
+    pub class: String,
+    pub id: Option<String>,
+    pub key: Option<String>,
+    pub aggregate: Option<Aggregate>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Aggregate {
+    Count, // number of entries, or of non-missing values
+    Sum,
+    Mean,
+    Min,
+    Max,
+    Any, // whether any value is `TRUE`
+}
+
+impl Reference {
+    // `Class$key` or `Class[id]$key`,
+    // or an aggregation over descendants such as `sum(Class$key)` or `count(Class)`
+    pub fn parse(value: &str) -> Option<Self> {
+        let function = value
+            .strip_suffix(')')
+            .and_then(|v| v.split_once('('))
+            .and_then(|(name, inner)| Aggregate::from(name).map(|a| (a, inner)));
+
+        match function {
+            Some((aggregate, inner)) => match Self::parse_target(inner) {
+                // aggregations are taken over every descendant of the class
+                Some(r) if r.id.is_some() => None,
+                Some(r) if r.key.is_none() && aggregate != Aggregate::Count => None,
+                Some(r) => Some(Self { aggregate: Some(aggregate), ..r }),
+                None => None,
+            },
+            None => Self::parse_target(value).filter(|r| r.key.is_some()),
+        }
+    }
+
+    // `Class`, `Class$key` or `Class[id]$key`
+    fn parse_target(target: &str) -> Option<Self> {
+        let (target, key) = match target.split_once('$') {
+            Some((_, k)) if k.is_empty() || k.contains('$') => return None,
+            Some((t, k)) => (t, Some(k.to_string())),
+            None => (target, None),
+        };
+        let (class, id) = match target.strip_suffix(']').and_then(|t| t.split_once('[')) {
+            Some((class, id)) if !id.is_empty() => (class, Some(id.to_string())),
+            Some(_) => return None,
+            None => (target, None),
+        };
+        if class.is_empty() {
+            return None;
+        }
+        Some(Self {
+            class: class.to_string(),
+            id,
+            key,
+            aggregate: None,
+        })
+    }
+}
+
+impl Aggregate {
+    pub fn from(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(Self::Count),
+            "sum" => Some(Self::Sum),
+            "mean" => Some(Self::Mean),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "any" => Some(Self::Any),
+            _ => None,
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
@@ -83,63 +227,34 @@ pub struct List {
 }
 
 impl Entry {
-    pub fn new(class: String, level: usize, parent_class: Option<String>, decorators: Option<Vec<Decorator>>) -> Self {
+    pub fn new(class: String, level: usize, parent_class: Option<String>, position: Marker, decorators: Option<Vec<Decorator>>) -> Self {
         Entry {
             class,
             parent_class,
             level,
+            position,
+            id: None,
+            foreign_keys: Vec::new(),
 
             decorators,
+            annotation: None,
 
             variables: VariableMap::new(),
             subentries: vec![],
 
             keys: Vec::new(),
+            optional_keys: Vec::new(),
+            typed_keys: Vec::new(),
             ref_keys: Vec::new(),
         }
     }
 
-    pub fn resolve_downstream_ref(&mut self, downstream: &HashMap<String, Vec<VariableMap>>) -> Result<(), ReamError> {
-        for key in &self.ref_keys {
-            let (ref_class, ref_key) = match self.variables.get(key) {
-                Some(v) => {
-                    match v.get_base() {
-                        ValueBase::Ref(class, key) => (class, key),
-                        _ => return Err(ReamError::Placeholder), // TODO: un!
-                    }
-                }
-                _ => return Err(ReamError::Placeholder), // TODO: un!
-            };
-            let value = match downstream.get(&ref_class) {
-                Some(list) => {
-                    let mut items: Vec<Value> = Vec::new();
-                    for item in list {
-                        let new_value = match item.get(&ref_key) {
-                            Some(s) => s.clone(),
-                            None => return Err(ReamError::ReferenceError(ReferenceErrorType::VariableKeyNotFound)),
-                        };
-                        items.push(new_value);
-                    }
-                    // let items = list.iter()
-                    //     .map(|hm| hm.get(&ref_key).unwrap().clone()) // TODO: clone!
-                    //     .collect::<Vec<Value>>();
-
-                    let (value_base, typ) = List::set_list(items);
-                    let value = Value::new(
-                        value_base,
-                        None,
-                        ValueType::List(Box::new(typ)),
-                    );
-
-                    value
-                },
-                None => return Err(ReamError::ReferenceError(
-                    ReferenceErrorType::EntryClassNotFound
-                )),
-            };
-            self.variables.insert(key.to_string(), value);
-        }
-        Ok(())
+    pub fn annotation(&self) -> Option<&String> {
+        self.annotation.as_ref()
+    }
+
+    pub fn set_annotation(&mut self, annotation: Option<String>) {
+        self.annotation = annotation;
     }
 
     pub fn set_ref_key(&mut self, keys: Vec<String>) {
@@ -150,14 +265,97 @@ impl Entry {
         self.ref_keys.clone()
     }
 
+    pub fn is_ref_key(&self, key: &String) -> bool {
+        self.ref_keys.contains(key)
+    }
+
+    pub fn subentries(&self) -> &Vec<Entry> {
+        &self.subentries
+    }
+
+    // entry at `path`, the indices of subentries from `self`
+    pub fn entry_at(&self, path: &[usize]) -> Option<&Entry> {
+        match path {
+            [] => Some(self),
+            [i, rest @ ..] => self.subentries.get(*i)?.entry_at(rest),
+        }
+    }
+
+    pub fn entry_at_mut(&mut self, path: &[usize]) -> Option<&mut Entry> {
+        match path {
+            [] => Some(self),
+            [i, rest @ ..] => self.subentries.get_mut(*i)?.entry_at_mut(rest),
+        }
+    }
+
     pub fn get_schema(&self) -> EntrySchema {
-        EntrySchema::new(self.keys.clone(), self.parent_class.clone())
+        let fields = self.keys
+            .iter()
+            .map(|key| {
+                let (typ, unit) = match self.variables.get(key) {
+                    Some(v) => (v.typ().clone(), v.unit().cloned()),
+                    None => (ValueType::Unknown, None),
+                };
+                let optional = self.optional_keys.contains(key);
+                FieldSchema::new(key.clone(), typ, optional, self.position)
+                    .with_unit(unit)
+                    .with_declared(self.typed_keys.contains(key))
+            })
+            .collect();
+        let mut schema = EntrySchema::new(fields, self.parent_class.clone(), self.position);
+        schema.id_key = self.id_key();
+        schema.foreign_keys = self.declared_foreign_keys();
+        schema.parents = self.declared_parents();
+        schema
+    }
+
+    // Align keys with the class schema,
+    // filling in `NA` for every optional key the entry omits.
+    pub fn fill_missing(&mut self, schemas: &HashMap<String, EntrySchema>) {
+        if let Some(schema) = schemas.get(&self.class) {
+            for field in schema.fields() {
+                if self.variables.get(field.key()).is_none() {
+                    let span = Span::new(self.position, self.position);
+                    let value = Value::new(ValueBase::Na(None), None, field.typ().clone(), span)
+                        .with_unit(field.unit().cloned());
+                    self.variables.insert(field.key().clone(), value);
+                }
+                if field.is_optional() && !self.optional_keys.contains(field.key()) {
+                    self.optional_keys.push(field.key().clone());
+                }
+            }
+            self.keys = schema.keys();
+        }
+
+        for subentry in self.subentries.iter_mut() {
+            subentry.fill_missing(schemas);
+        }
+    }
+
+    pub fn position(&self) -> Marker {
+        self.position
     }
 
     pub fn push_key(&mut self, key: String) {
         self.keys.push(key);
     }
 
+    pub fn push_optional_key(&mut self, key: String) {
+        self.optional_keys.push(key);
+    }
+
+    pub fn is_optional(&self, key: &String) -> bool {
+        self.optional_keys.contains(key)
+    }
+
+    pub fn push_typed_key(&mut self, key: String) {
+        self.typed_keys.push(key);
+    }
+
+    pub fn is_typed(&self, key: &String) -> bool {
+        self.typed_keys.contains(key)
+    }
+
     pub fn push_subentry(&mut self, subentry: Entry) {
         self.subentries.push(subentry);
     }
@@ -166,18 +364,124 @@ impl Entry {
         self.parent_class.clone() // TODO: clone!
     }
 
-    pub fn get_variable_values(&self) -> Vec<String> {
-        let mut output: Vec<String> = Vec::new();
-        for key in self.keys.clone() {
-            match self.value(&key) {
-                Some(item) => {
-                    let item_string = item.get_raw();
-                    output.push(item_string);
-                },
-                None => {}
+    pub fn id(&self) -> Option<&String> {
+        self.id.as_ref()
+    }
+
+    // Assign identifiers from `@ ID(...)`,
+    // or from the key declared by `@ ID_KEY(...)` for the class,
+    // and the foreign keys of the class.
+    pub fn assign_ids(&mut self, schemas: &HashMap<String, EntrySchema>) {
+        let literal = self.decorators.iter().flatten().find_map(|d| match d {
+            Decorator::Id(id) => Some(id.clone()),
+            _ => None,
+        });
+        let id_key = schemas.get(&self.class).and_then(|s| s.id_key());
+        self.id = match (literal, id_key) {
+            (Some(id), _) => Some(id),
+            (None, Some(key)) => match self.variables.get(key) {
+                Some(v) if !v.is_na() => Some(v.get_raw()),
+                _ => None,
+            },
+            (None, None) => None,
+        };
+
+        if let Some(schema) = schemas.get(&self.class) {
+            self.foreign_keys = schema.foreign_keys().clone();
+        }
+
+        for subentry in self.subentries.iter_mut() {
+            subentry.assign_ids(schemas);
+        }
+    }
+
+    pub fn foreign_keys(&self) -> &Vec<ForeignKey> {
+        &self.foreign_keys
+    }
+
+    pub fn foreign_key(&self, key: &str) -> Option<&ForeignKey> {
+        self.foreign_keys.iter().find(|fk| fk.key == key)
+    }
+
+    // foreign keys declared by `@ FOREIGN_KEY(...)`
+    pub fn declared_foreign_keys(&self) -> Vec<ForeignKey> {
+        self.decorators.iter().flatten().filter_map(|d| match d {
+            Decorator::ForeignKey(key, class) => Some(ForeignKey {
+                key: key.clone(),
+                class: class.clone(),
+            }),
+            _ => None,
+        }).collect()
+    }
+
+    // parent classes permitted by `@ PARENTS(...)`, or by `@ RECURSIVE`:
+    // the parent of the entry and the class itself
+    pub fn declared_parents(&self) -> Vec<String> {
+        let mut parents = Vec::new();
+        for decorator in self.decorators.iter().flatten() {
+            let classes = match decorator {
+                Decorator::Parents(classes) => classes.clone(),
+                Decorator::Recursive => self.parent_class.iter().cloned().chain(Some(self.class.clone())).collect(),
+                _ => continue,
+            };
+            for class in classes {
+                if !parents.contains(&class) {
+                    parents.push(class);
+                }
+            }
+        }
+        parents
+    }
+
+    // key declared by `@ ID_KEY(...)`
+    pub fn id_key(&self) -> Option<String> {
+        self.decorators.iter().flatten().find_map(|d| match d {
+            Decorator::IdKey(key) => Some(key.clone()),
+            _ => None,
+        })
+    }
+
+    // categorical types declared by `@ LEVELS(...)`, in order
+    pub fn levels(&self) -> Vec<(String, Vec<String>)> {
+        self.decorators.iter().flatten().filter_map(|d| match d {
+            Decorator::Levels(name, levels) => Some((name.clone(), levels.clone())),
+            _ => None,
+        }).collect()
+    }
+
+    // constraints declared by `@ CHECK(...)`, as class, key and text
+    pub fn constraints(&self) -> Vec<(String, String, String)> {
+        self.decorators.iter().flatten().filter_map(|d| match d {
+            Decorator::Check(class, key, text) => Some((class.clone(), key.clone(), text.clone())),
+            _ => None,
+        }).collect()
+    }
+
+    // literal profile selected by `@ LITERALS(...)`
+    pub fn literal_profile(&self) -> Option<LiteralProfile> {
+        self.decorators.iter().flatten().find_map(|d| match d {
+            Decorator::Literals(name) => LiteralProfile::named(name),
+            _ => None,
+        })
+    }
+
+    // decorators declaring properties of the whole dataset, e.g. `@ MISSING(...)`
+    pub fn dataset_decorators(&self) -> Vec<&Decorator> {
+        self.decorators.iter().flatten().filter(|d| d.is_dataset_level()).collect()
+    }
+
+    // codes declared by `@ MISSING(...)`
+    pub fn missing_codes(&self) -> Vec<String> {
+        let decorators = match &self.decorators {
+            Some(ds) => ds,
+            None => return Vec::new(),
+        };
+        for decorator in decorators {
+            if let Decorator::Missing(codes) = decorator {
+                return codes.clone();
             }
         }
-        output
+        Vec::new()
     }
 
     pub fn class(&self) -> String {
@@ -204,15 +508,113 @@ impl Entry {
         self.variables.get(key)
     }
 
+    // Default value for a key the entry omits.
+    // The key is placed as in the schema of the class, if any.
+    pub fn inherit(&mut self, key: String, value: Value, schema: Option<&EntrySchema>) {
+        if self.variables.get(&key).is_some() {
+            return;
+        }
+        let schema_keys = schema.map(|s| s.keys()).unwrap_or_default();
+        let index = match schema_keys.iter().position(|k| *k == key) {
+            // after the last key preceding it in the schema
+            Some(i) => match self.keys.iter().rposition(|k| schema_keys[..i].contains(k)) {
+                Some(j) => j + 1,
+                None => 0,
+            },
+            None => self.keys.len(),
+        };
+        self.keys.insert(index, key.clone());
+        self.variables.insert(key, value.with_provenance(Provenance::Inherited));
+    }
+
+    // replace the value of an existing key
+    pub fn set_value(&mut self, key: String, value: Value) {
+        self.variables.insert(key, value);
+    }
+
+    // Move the entry, its values and its subentries below line `line` by `delta` lines,
+    // as after an edit inserting or deleting lines.
+    pub fn shift_lines(&mut self, line: usize, delta: isize) {
+        self.position = self.position.shifted(line, delta);
+        for value in self.variables.map.values_mut() {
+            value.shift_lines(line, delta);
+        }
+        for subentry in &mut self.subentries {
+            subentry.shift_lines(line, delta);
+        }
+    }
+
 
 }
 
 impl Value {
-    pub fn new(value: ValueBase, annotation: Option<String>, typ: ValueType) -> Self {
+    pub fn new(value: ValueBase, annotation: Option<String>, typ: ValueType, span: Span) -> Self {
         Self {
             value,
             annotation,
             typ,
+            span,
+            provenance: Provenance::Explicit,
+            unit: None,
+            literal: None,
+        }
+    }
+
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    pub fn with_unit(mut self, unit: Option<String>) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    pub fn unit(&self) -> Option<&String> {
+        self.unit.as_ref()
+    }
+
+    pub fn with_annotation(mut self, annotation: Option<String>) -> Self {
+        self.annotation = annotation;
+        self
+    }
+
+    pub fn with_literal(mut self, literal: Option<String>) -> Self {
+        self.literal = literal;
+        self
+    }
+
+    pub fn literal(&self) -> Option<&String> {
+        self.literal.as_ref()
+    }
+
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    pub fn origin(&self) -> Option<&Origin> {
+        match &self.provenance {
+            Provenance::Referenced(origin) => Some(origin),
+            _ => None,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    // the value, its items and the origin of a reference below line `line`, moved by `delta` lines
+    fn shift_lines(&mut self, line: usize, delta: isize) {
+        self.span = self.span.shifted(line, delta);
+        if let Provenance::Referenced(origin) = &mut self.provenance {
+            if origin.line > line {
+                origin.line = origin.line.saturating_add_signed(delta);
+            }
+        }
+        if let ValueBase::List(list) = &mut self.value {
+            for item in &mut list.items {
+                item.shift_lines(line, delta);
+            }
         }
     }
 
@@ -239,32 +641,138 @@ impl Value {
     pub fn get_raw(&self) -> String {
         self.value.get_raw()
     }
+
+    // numbers only; `None` for any other value
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match &self.value {
+            ValueBase::Num(n) => Some(n.decimal()),
+            _ => None,
+        }
+    }
+
+    pub fn as_interval(&self) -> Option<&Interval> {
+        match &self.value {
+            ValueBase::Interval(interval) => Some(interval),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&List> {
+        match &self.value {
+            ValueBase::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    // lower bound, upper bound and point of numbers and intervals;
+    // a number is its own bounds
+    pub fn bounds(&self) -> Option<(Decimal, Decimal, Option<Decimal>)> {
+        self.value.bounds()
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_decimal().map(|d| d.as_f64())
+    }
+
+    // `None` unless a whole number within `i64`
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_decimal().and_then(|d| d.as_i64())
+    }
+
+    pub fn is_na(&self) -> bool {
+        matches!(self.value, ValueBase::Na(_))
+    }
+
+    pub fn missing_code(&self) -> Option<&String> {
+        match &self.value {
+            ValueBase::Na(code) => code.as_ref(),
+            _ => None,
+        }
+    }
 }
 
 impl ValueType {
+    // Combine two types seen for the same variable or list item.
+    // `Unknown`, the type of an untyped `NA`, is compatible with any type.
+    // `int` widens to `num`.
+    pub fn merge(&self, other: &ValueType) -> Option<ValueType> {
+        match (self, other) {
+            (Self::Unknown, t) | (t, Self::Unknown) => Some(t.clone()),
+            (Self::Int, Self::Num) | (Self::Num, Self::Int) => Some(Self::Num),
+            (Self::List(a), Self::List(b)) => a.merge(b).map(|t| Self::List(Box::new(t))),
+            (Self::Ref(a), Self::Ref(b)) => a.merge(b).map(|t| Self::Ref(Box::new(t))),
+            (a, b) if a == b => Some(a.clone()),
+            _ => None,
+        }
+    }
+
+    // whether merging into `other` turns `int` into `num`, also of items and references
+    pub fn widens_to(&self, other: &ValueType) -> bool {
+        match (self, other) {
+            (Self::Int, Self::Num) => true,
+            (Self::List(a), Self::List(b)) | (Self::Ref(a), Self::Ref(b)) => a.widens_to(b),
+            _ => false,
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Self::Num | Self::Int)
+    }
+
+    pub fn is_date(&self) -> bool {
+        matches!(self, Self::Date | Self::DateTime | Self::Year | Self::YearMonth)
+    }
+
     pub fn size(&self) -> usize {
         match self {
             Self::Unknown => 0,
             Self::Str => 3,
             Self::Num => 3,
+            Self::Int => 3,
             Self::Bool => 4,
+            Self::Date => 4,
+            Self::DateTime => 8,
+            Self::Year => 4,
+            Self::YearMonth => 9,
+            Self::Cat(name) => name.len() + 4,
+            Self::Custom(name) => name.len(),
             Self::List(u) => (*u).size() + 5,
-            Self::Ref => 3,
+            Self::Ref(u) => match **u {
+                Self::Unknown => 3,
+                _ => (*u).size() + 4,
+            },
         }
     }
 }
 
 impl ValueBase {
+    // lower bound, upper bound and point of numbers and intervals
+    pub fn bounds(&self) -> Option<(Decimal, Decimal, Option<Decimal>)> {
+        match self {
+            Self::Num(n) => Some((n.decimal(), n.decimal(), Some(n.decimal()))),
+            Self::Interval(i) => Some((i.lower().decimal(), i.upper().decimal(), i.point().map(|p| p.decimal()))),
+            _ => None,
+        }
+    }
+
     pub fn new(val: String, typ: ValueType) -> Result<(Self, ValueType), ReamError> {
         match typ {
             // Value type is not specified.
-            // Check for `bool` and `num`.
+            // Check for `bool`, `int` and `num`, without exponents.
             // If netiher, return `str`.
             ValueType::Unknown => {
+                let number = match is_decimal(&val) {
+                    true => Number::parse(&val),
+                    false => None,
+                };
                 if is_bool(&val) {
                     Ok((Self::Bool(val), ValueType::Bool))
-                } else if is_num(&val) {
-                    Ok((Self::Num(val), ValueType::Num))
+                } else if let Some(interval) = Interval::parse(&val) {
+                    let typ = if interval.is_integral() { ValueType::Int } else { ValueType::Num };
+                    Ok((Self::Interval(Box::new(interval)), typ))
+                } else if let Some(number) = number {
+                    let typ = if is_int(&val) { ValueType::Int } else { ValueType::Num };
+                    Ok((Self::Num(number), typ))
                 } else {
                     Ok((Self::Str(val), ValueType::Str))
                 }
@@ -273,10 +781,25 @@ impl ValueBase {
             // Value type is specified.
             // Validate value type.
             ValueType::Num => {
-                if !is_num(&val) {
-                    return Err(ReamError::TypeError(TypeErrorType::InvalidNumber));
+                if let Some(interval) = Interval::parse(&val) {
+                    return Ok((Self::Interval(Box::new(interval)), typ));
+                }
+                match Number::parse(&val) {
+                    Some(number) if is_num(&val) => return Ok((Self::Num(number), typ)),
+                    _ => return Err(ReamError::TypeError(TypeErrorType::InvalidNumber)),
+                }
+            }
+
+            ValueType::Int => {
+                match Interval::parse(&val) {
+                    Some(interval) if interval.is_integral() => return Ok((Self::Interval(Box::new(interval)), typ)),
+                    Some(_) => return Err(ReamError::TypeError(TypeErrorType::InvalidInteger)),
+                    None => {}
+                }
+                match Number::parse(&val) {
+                    Some(number) if is_int(&val) => return Ok((Self::Num(number), typ)),
+                    _ => return Err(ReamError::TypeError(TypeErrorType::InvalidInteger)),
                 }
-                return Ok((Self::Num(val), typ));
             }
 
             ValueType::Bool => {
@@ -288,6 +811,13 @@ impl ValueBase {
 
             ValueType::Str => return Ok((Self::Str(val), typ)),
 
+            ValueType::Date | ValueType::DateTime | ValueType::Year | ValueType::YearMonth => {
+                if !is_date(&val, &typ) {
+                    return Err(ReamError::TypeError(TypeErrorType::InvalidDate));
+                }
+                return Ok((Self::Date(val), typ));
+            }
+
             ValueType::List(t) => return Err(ReamError::Placeholder),
 
             _ => return Err(ReamError::Placeholder),
@@ -298,18 +828,23 @@ impl ValueBase {
         ValueBase::List(Box::new(list))
     }
 
-    pub fn new_ref(class: String, key: String) -> Self {
-        Self::Ref(class, key)
+    pub fn new_ref(reference: Reference) -> Self {
+        Self::Ref(reference)
     }
 
     pub fn get_raw(&self) -> String {
         match self {
             Self::Str(s) => s.to_string(),
-            Self::Num(s) => s.to_string(),
+            Self::Num(n) => n.lexeme().to_string(),
+            Self::Interval(interval) => interval.to_string(),
             Self::Bool(s) => s.to_string(),
+            Self::Date(s) => s.to_string(),
             Self::Unknown(s) => s.to_string(),
             Self::List(list) => list.items_as_string(),
-            Self::Ref(_, _) => "UnreachablePlaceholder".to_string(),
+            Self::Ref(reference) => reference.to_string(),
+            Self::Na(None) => "NA".to_string(),
+            Self::Na(Some(code)) => format!("NA({})", code),
+            Self::Expr(expression) => expression.to_string(),
         }
     }
 
@@ -331,19 +866,43 @@ impl List {
         &self.item_typ
     }
 
+    pub fn items(&self) -> &Vec<Value> {
+        &self.items
+    }
+
+    pub fn last_item_span(&self) -> Span {
+        match self.items.last() {
+            Some(item) => item.span(),
+            None => unreachable!(), // lists are never empty
+        }
+    }
+
+    pub fn set_item_type(&mut self, typ: ValueType) {
+        self.item_typ = typ;
+    }
+
+    // items joined by `;`, sub-lists in brackets: `[1;2];[3]`
     pub fn items_as_string(&self) -> String {
         self.items
             .iter()
-            .map(|item| item.get_value())
+            .map(|item| match &item.value {
+                ValueBase::List(list) => format!("[{}]", list.items_as_string()),
+                _ => item.get_value(),
+            })
             .collect::<Vec<String>>()
             .join(";")
     }
 
-    pub fn set_list(items: Vec<Value>) -> (ValueBase, ValueType) {
-        let (_, typ) = items[0].get_base_and_typ();
-        let item_typ = ValueType::List(Box::new(typ.clone())); // TODO: clone!
-        let list = Self { item_typ, items };
-        (ValueBase::List(Box::new(list)), typ)
+    pub fn set_list(items: Vec<Value>) -> Result<(ValueBase, ValueType), ReamError> {
+        let mut item_typ = ValueType::Unknown;
+        for item in &items {
+            item_typ = match item_typ.merge(item.typ()) {
+                Some(t) => t,
+                None => return Err(ReamError::TypeError(TypeErrorType::HeterogeneousList)),
+            };
+        }
+        let list = Self { item_typ: item_typ.clone(), items };
+        Ok((ValueBase::List(Box::new(list)), item_typ))
     }
 }
 
@@ -370,31 +929,275 @@ fn is_bool(value: &str) -> bool {
     }
 }
 
-fn is_num(value: &str) -> bool {
-    // let re = Regex::new(r"^[+-]?([0-9]*[.])?[0-9]+$").unwrap();
-    // re.is_match(value)
-    match value.parse::<f64>() {
-        Ok(_) => true,
-        _ => false,
+// `[+-]digits[.digits][e[+-]digits]`, finite; no `inf` or `NaN`
+pub fn is_num(value: &str) -> bool {
+    let (mantissa, exponent) = match value.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e)),
+        None => (value, None),
+    };
+    let exponent_ok = match exponent {
+        Some(e) => is_digits(e.strip_prefix(['+', '-']).unwrap_or(e)),
+        None => true,
+    };
+    is_decimal(mantissa)
+        && exponent_ok
+        && value.parse::<f64>().is_ok_and(|n| n.is_finite())
+}
+
+// `[+-]digits[.digits]`, or `[+-].digits` as in `.5`
+pub fn is_decimal(value: &str) -> bool {
+    let unsigned = value.strip_prefix(['+', '-']).unwrap_or(value);
+    match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer.is_empty() || is_digits(integer)) && is_digits(fraction),
+        None => is_digits(unsigned),
+    }
+}
+
+// `[+-]digits`
+pub fn is_int(value: &str) -> bool {
+    is_digits(value.strip_prefix(['+', '-']).unwrap_or(value))
+}
+
+fn is_digits(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+// `+007.50` as `7.5`, `1.0E+05` as `1e5`
+pub fn canonical_num(value: &str) -> String {
+    let (mantissa, exponent) = match value.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e)),
+        None => (value, None),
+    };
+    let (negative, unsigned) = match mantissa.strip_prefix('-') {
+        Some(m) => (true, m),
+        None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let integer = match integer.trim_start_matches('0') {
+        "" => "0",
+        i => i,
+    };
+    let fraction = fraction.trim_end_matches('0');
+
+    let mut canonical = integer.to_string();
+    if !fraction.is_empty() {
+        canonical += &format!(".{}", fraction);
+    }
+    if canonical == "0" {
+        return canonical;
+    }
+    if negative {
+        canonical = format!("-{}", canonical);
     }
+    if let Some(e) = exponent {
+        let (sign, digits) = match e.strip_prefix('-') {
+            Some(d) => ("-", d),
+            None => ("", e.strip_prefix('+').unwrap_or(e)),
+        };
+        match digits.trim_start_matches('0') {
+            "" => {}
+            d => canonical += &format!("e{}{}", sign, d),
+        }
+    }
+    canonical
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EntrySchema {
-    keys: Vec<String>,
+    fields: Vec<FieldSchema>,
+    id_key: Option<String>,
+    foreign_keys: Vec<ForeignKey>,
     parent_class: Option<String>,
+    parents: Vec<String>, // permitted parent classes, if declared
+    position: Marker, // the entry that defined the schema
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSchema {
+    key: String,
+    typ: ValueType,
+    optional: bool,
+    position: Marker, // the entry that defined the type
+    unit: Option<String>,
+    declared: bool, // typed in some entry, so it does not widen
+}
+
+impl FieldSchema {
+    pub fn new(key: String, typ: ValueType, optional: bool, position: Marker) -> Self {
+        Self { key, typ, optional, position, unit: None, declared: false }
+    }
+
+    pub fn with_unit(mut self, unit: Option<String>) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    pub fn with_declared(mut self, declared: bool) -> Self {
+        self.declared = declared;
+        self
+    }
+
+    pub fn unit(&self) -> Option<&String> {
+        self.unit.as_ref()
+    }
+
+    pub fn key(&self) -> &String {
+        &self.key
+    }
+
+    pub fn typ(&self) -> &ValueType {
+        &self.typ
+    }
+
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
 }
 
 impl EntrySchema {
-    pub fn new(keys: Vec<String>, parent_class: Option<String>) -> Self {
-        Self { keys, parent_class }
+    pub fn new(fields: Vec<FieldSchema>, parent_class: Option<String>, position: Marker) -> Self {
+        Self { fields, id_key: None, foreign_keys: Vec::new(), parent_class, parents: Vec::new(), position }
+    }
+
+    // Entries of the class share their parent class,
+    // unless parent classes are declared.
+    pub fn permits_parent(&self, parent: &Option<String>) -> bool {
+        if self.parents.is_empty() {
+            return self.parent_class == *parent;
+        }
+        match parent {
+            Some(p) => self.parents.contains(p),
+            None => false,
+        }
+    }
+
+    pub fn foreign_keys(&self) -> &Vec<ForeignKey> {
+        &self.foreign_keys
+    }
+
+    pub fn id_key(&self) -> Option<&String> {
+        self.id_key.as_ref()
     }
 
     pub fn keys(&self) -> Vec<String> {
-        self.keys.clone() // TODO: clone!
+        self.fields.iter().map(|f| f.key.clone()).collect() // TODO: clone!
+    }
+
+    pub fn fields(&self) -> &Vec<FieldSchema> {
+        &self.fields
+    }
+
+    pub fn field(&self, key: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|f| f.key == key)
     }
 
     pub fn get_parent_class(&self) -> Option<String> {
         self.parent_class.clone() // TODO: clone
     }
+
+    pub fn position(&self) -> Marker {
+        self.position
+    }
+
+    // Merge `other` into `self`, the schema of the class.
+    //
+    // Required keys must be present in every entry and optional keys may be omitted.
+    // Keys must follow the order of the schema; a new key is accepted only if
+    // it is marked optional, and is inserted after the key preceding it.
+    // Every key must keep the type first seen for the class,
+    // with `NA` compatible with any type. Untyped `int` keys widen to `num`,
+    // but a key declared `(int)` in any entry does not.
+    pub fn merge(&mut self, class: &str, other: &EntrySchema) -> Result<(), ReamError> {
+        // permitted parents may be declared by any entry
+        for parent in &other.parents {
+            if !self.parents.contains(parent) {
+                self.parents.push(parent.clone());
+            }
+        }
+        if !self.permits_parent(&other.parent_class) {
+            return Err(ReamError::SchemaError(SchemaErrorType::IncorrectParentClass));
+        }
+
+        // the identifier key may be declared by any entry, but only once
+        match (&self.id_key, &other.id_key) {
+            (Some(a), Some(b)) if a != b => {
+                return Err(ReamError::SchemaError(SchemaErrorType::IncorrectIdKey));
+            }
+            (None, Some(_)) => self.id_key = other.id_key.clone(),
+            _ => {}
+        }
+
+        // so may foreign keys
+        for fk in &other.foreign_keys {
+            match self.foreign_keys.iter().find(|f| f.key == fk.key) {
+                Some(f) if f.class != fk.class => {
+                    return Err(ReamError::SchemaError(SchemaErrorType::IncorrectForeignKey));
+                }
+                Some(_) => {}
+                None => self.foreign_keys.push(fk.clone()),
+            }
+        }
+
+        let mut cursor = 0; // index of the next schema field to match
+        for field in &other.fields {
+            match self.fields[cursor..].iter().position(|f| f.key == field.key) {
+                Some(offset) => {
+                    // skipped fields must be optional
+                    if self.fields[cursor..cursor + offset].iter().any(|f| !f.optional) {
+                        return Err(ReamError::SchemaError(SchemaErrorType::IncorrectKeys));
+                    }
+                    cursor += offset;
+                    let expected = &mut self.fields[cursor];
+                    let widens = |f: &FieldSchema, t: &ValueType| f.declared && f.typ.widens_to(t);
+                    let typ = match expected.typ.merge(&field.typ) {
+                        Some(t) if !widens(expected, &t) && !widens(field, &t) => t,
+                        _ => return Err(ReamError::SchemaError(SchemaErrorType::IncompatibleTypes(TypeConflict {
+                            class: class.to_string(),
+                            key: field.key.clone(),
+                            expected: expected.typ.clone(),
+                            expected_at: expected.position,
+                            found: field.typ.clone(),
+                            found_at: field.position,
+                        }))),
+                    };
+                    // untyped `NA` has no unit to compare
+                    if expected.typ == ValueType::Unknown {
+                        expected.unit = field.unit.clone();
+                    } else if field.typ != ValueType::Unknown && expected.unit != field.unit {
+                        return Err(ReamError::SchemaError(SchemaErrorType::MixedUnits(
+                            Box::new(UnitConflict {
+                                class: class.to_string(),
+                                key: field.key.clone(),
+                                expected: expected.unit.clone(),
+                                expected_at: expected.position,
+                                found: field.unit.clone(),
+                                found_at: field.position,
+                            })
+                        )));
+                    }
+                    if typ != expected.typ {
+                        expected.typ = typ;
+                        expected.position = field.position;
+                    }
+                    expected.optional |= field.optional;
+                    expected.declared |= field.declared;
+                }
+                None => {
+                    // unknown or out-of-order key
+                    if !field.optional || self.field(&field.key).is_some() {
+                        return Err(ReamError::SchemaError(SchemaErrorType::IncorrectKeys));
+                    }
+                    self.fields.insert(cursor, field.clone());
+                }
+            }
+            cursor += 1;
+        }
+
+        // remaining fields must be optional
+        if self.fields[cursor..].iter().any(|f| !f.optional) {
+            return Err(ReamError::SchemaError(SchemaErrorType::IncorrectKeys));
+        }
+
+        Ok(())
+    }
 }
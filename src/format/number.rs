@@ -0,0 +1,269 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+// A number as written, e.g. `0.590`, with its exact decimal value.
+// Serialized as written, `"0.590"`, which keeps every digit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Number {
+    lexeme: String,
+    decimal: Decimal,
+}
+
+// `coefficient * 10^exponent`, exact up to 38 significant digits.
+// Equal and ordered by value: `1.50` equals `1.5`.
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    coefficient: i128,
+    exponent: i32,
+}
+
+impl Number {
+    // `None` if the number has more than 38 significant digits
+    pub fn parse(lexeme: &str) -> Option<Self> {
+        Some(Self {
+            lexeme: lexeme.to_string(),
+            decimal: Decimal::parse(lexeme)?,
+        })
+    }
+
+    // the result of a computation, written without trailing zeros
+    pub fn from_decimal(decimal: Decimal) -> Self {
+        let decimal = decimal.normalize();
+        Self {
+            lexeme: decimal.to_string(),
+            decimal,
+        }
+    }
+
+    pub fn from_f64(n: f64) -> Option<Self> {
+        Decimal::from_f64(n).map(Self::from_decimal)
+    }
+
+    pub fn lexeme(&self) -> &str {
+        &self.lexeme
+    }
+
+    pub fn decimal(&self) -> Decimal {
+        self.decimal
+    }
+
+    // the same number, written as `7.5` for `+007.50`
+    pub fn canonical(&self) -> Self {
+        Self {
+            lexeme: crate::format::canonical_num(&self.lexeme),
+            decimal: self.decimal,
+        }
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Self {
+        Self::from_decimal(Decimal { coefficient: n as i128, exponent: 0 })
+    }
+}
+
+impl Decimal {
+    // `[+-]digits[.digits][e[+-]digits]`
+    pub fn parse(value: &str) -> Option<Self> {
+        let (mantissa, exponent) = match value.split_once(['e', 'E']) {
+            Some((m, e)) => (m, e.parse::<i32>().ok()?),
+            None => (value, 0),
+        };
+        let (negative, unsigned) = match mantissa.strip_prefix('-') {
+            Some(m) => (true, m),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+        let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let digits = format!("{}{}", integer, fraction);
+        let digits = digits.trim_start_matches('0');
+        if digits.len() > 38 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let coefficient = if digits.is_empty() { 0 } else { digits.parse::<i128>().ok()? };
+        Some(Self {
+            coefficient: if negative { -coefficient } else { coefficient },
+            exponent: exponent.checked_sub(fraction.len() as i32)?,
+        })
+    }
+
+    pub fn from_f64(n: f64) -> Option<Self> {
+        if !n.is_finite() {
+            return None;
+        }
+        // shortest digits that round-trip
+        Self::parse(&format!("{:e}", n))
+    }
+
+    pub fn zero() -> Self {
+        Self { coefficient: 0, exponent: 0 }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefficient == 0
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        format!("{}e{}", self.coefficient, self.exponent).parse().unwrap_or(f64::NAN)
+    }
+
+    // `None` unless a whole number within `i64`
+    pub fn as_i64(&self) -> Option<i64> {
+        let normal = self.normalize();
+        if normal.exponent < 0 {
+            return None;
+        }
+        let scale = 10i128.checked_pow(normal.exponent as u32)?;
+        i64::try_from(normal.coefficient.checked_mul(scale)?).ok()
+    }
+
+    // without trailing zeros in the coefficient
+    pub fn normalize(&self) -> Self {
+        if self.coefficient == 0 {
+            return Self::zero();
+        }
+        let mut normal = *self;
+        while normal.coefficient % 10 == 0 {
+            normal.coefficient /= 10;
+            normal.exponent += 1;
+        }
+        normal
+    }
+
+    // exact unless the result overflows, then rounded as `f64`;
+    // `None` if beyond the range of `f64`
+    pub fn add(&self, other: &Self) -> Option<Self> {
+        match align(self, other).and_then(|(a, b, e)| Some((a.checked_add(b)?, e))) {
+            Some((coefficient, exponent)) => Some(Self { coefficient, exponent }),
+            None => Self::from_f64(self.as_f64() + other.as_f64()),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Option<Self> {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Option<Self> {
+        let exact = self.coefficient.checked_mul(other.coefficient)
+            .zip(self.exponent.checked_add(other.exponent));
+        match exact {
+            Some((coefficient, exponent)) => Some(Self { coefficient, exponent }),
+            None => Self::from_f64(self.as_f64() * other.as_f64()),
+        }
+    }
+
+    // exact if the quotient has a finite decimal expansion, otherwise rounded as `f64`;
+    // `None` when dividing by zero or beyond the range of `f64`
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        match quotient(self, other) {
+            Some(exact) if exact.as_f64().is_finite() => Some(exact),
+            Some(_) => None,
+            None => Self::from_f64(self.as_f64() / other.as_f64()),
+        }
+    }
+
+    pub fn neg(&self) -> Self {
+        Self { coefficient: -self.coefficient, exponent: self.exponent }
+    }
+}
+
+// `a / b`, if it terminates: once reduced, the divisor must have no prime factors but 2 and 5
+fn quotient(a: &Decimal, b: &Decimal) -> Option<Decimal> {
+    let divisor = i128::try_from(gcd(a.coefficient.unsigned_abs(), b.coefficient.unsigned_abs())).ok()?;
+    let (mut coefficient, mut d) = (a.coefficient / divisor, b.coefficient / divisor);
+    if d < 0 {
+        coefficient = coefficient.checked_neg()?;
+        d = d.checked_neg()?;
+    }
+    let mut exponent = a.exponent.checked_sub(b.exponent)?;
+    // `n / d` as `n * f / (d * f)`, where `d * f` is `d / 2 * 10` or `d / 5 * 10`
+    while d != 1 {
+        let f = match d {
+            _ if d % 2 == 0 => 5,
+            _ if d % 5 == 0 => 2,
+            _ => return None,
+        };
+        coefficient = coefficient.checked_mul(f)?;
+        d = d * f / 10;
+        exponent = exponent.checked_sub(1)?;
+    }
+    Some(Decimal { coefficient, exponent })
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+// coefficients of both numbers at the smaller exponent
+fn align(a: &Decimal, b: &Decimal) -> Option<(i128, i128, i32)> {
+    let exponent = a.exponent.min(b.exponent);
+    let scale = |d: &Decimal| {
+        let shift = u32::try_from(d.exponent.checked_sub(exponent)?).ok()?;
+        d.coefficient.checked_mul(10i128.checked_pow(shift)?)
+    };
+    Some((scale(a)?, scale(b)?, exponent))
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match align(self, other) {
+            Some((a, b, _)) => a.cmp(&b),
+            None => self.as_f64().total_cmp(&other.as_f64()),
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.coefficient < 0 { "-" } else { "" };
+        let digits = self.coefficient.unsigned_abs().to_string();
+        if self.exponent >= 0 {
+            return write!(f, "{}{}{}", sign, digits, "0".repeat(self.exponent as usize));
+        }
+        let scale = self.exponent.unsigned_abs() as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    }
+}
+
+impl Serialize for Number {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.lexeme)
+    }
+}
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let lexeme = String::deserialize(deserializer)?;
+        Number::parse(&lexeme).ok_or_else(|| D::Error::custom(format!("invalid number `{}`", lexeme)))
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.lexeme)
+    }
+}
@@ -4,6 +4,11 @@ use std::fmt;
 
 impl fmt::Display for Entry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(decorators) = &self.decorators {
+            for decorator in decorators {
+                writeln!(f, "{at} {decorator}", at = "@".repeat(self.level), decorator = decorator)?;
+            }
+        }
         let header = "#".repeat(self.level);
         let class = &self.class;
         write!(
@@ -12,18 +17,46 @@ impl fmt::Display for Entry {
             header = header,
             class = class,
         )?;
+        if let Some(annotation) = &self.annotation {
+            for line in annotation.lines() {
+                writeln!(f, "> {}", line)?;
+            }
+        }
         for key in &self.keys {
             let value = match self.variables.get(&key.to_string()) {
                 Some(v) =>  v,
                 None => unreachable!(),
             };
-            write!(
-                f,
-                "- {key} ({typ}): {value}\n",
-                key = key,
-                typ = value.typ,
-                value = value,
-            )?;
+            // inherited values are written once, by `@ DEFAULT(...)`
+            if *value.provenance() == Provenance::Inherited {
+                continue;
+            }
+            let mark = if self.is_optional(key) { "?" } else { "" };
+            let separator = match value.provenance() {
+                Provenance::Computed(_) => " =",
+                _ => ":",
+            };
+            match &value.typ {
+                // untyped `NA`
+                ValueType::Unknown => write!(
+                    f,
+                    "- {key}{mark}{separator} {value}\n",
+                    key = key,
+                    mark = mark,
+                    separator = separator,
+                    value = value,
+                )?,
+                typ => write!(
+                    f,
+                    "- {key}{mark} ({typ}{unit}){separator} {value}\n",
+                    key = key,
+                    mark = mark,
+                    typ = typ,
+                    unit = value.unit().map(|u| format!(" {}", u)).unwrap_or_default(),
+                    separator = separator,
+                    value = value,
+                )?,
+            }
         }
 
         for subentry in &self.subentries {
@@ -40,8 +73,18 @@ impl fmt::Display for ValueType {
         let typ = match &*self {
             Self::Str => "str".to_string(),
             Self::Num => "num".to_string(),
+            Self::Int => "int".to_string(),
             Self::Bool => "bool".to_string(),
-            Self::Ref => "ref".to_string(),
+            Self::Date => "date".to_string(),
+            Self::DateTime => "datetime".to_string(),
+            Self::Year => "year".to_string(),
+            Self::YearMonth => "yearmonth".to_string(),
+            Self::Cat(name) => format!("cat {}", name),
+            Self::Custom(name) => name.clone(),
+            Self::Ref(t) => match **t {
+                Self::Unknown => "ref".to_string(),
+                _ => format!("ref {}", t.to_string()),
+            },
             Self::List(t) => format!("list {}", t.to_string()),
             Self::Unknown => unreachable!(),
         };
@@ -54,35 +97,92 @@ impl fmt::Display for ValueBase {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let value = match &self {
             Self::Str(s) =>  format!("{}", s),
-            Self::Num(s) =>  format!("{}", s),
+            Self::Num(n) =>  format!("{}", n),
+            Self::Interval(interval) => format!("{}", interval),
             Self::Bool(s) => format!("{}", s),
+            Self::Date(s) => format!("{}", s),
             Self::List(list) => format!("{}", list),
             Self::Unknown(_) => unreachable!(),
-            Self::Ref(_, _) => unreachable!(),
+            Self::Ref(reference) => format!("{}", reference),
+            Self::Na(None) => "NA".to_string(),
+            Self::Na(Some(code)) => format!("NA({})", code),
+            Self::Expr(expression) => format!("{}", expression),
         };
         write!(f, "{}", value)?;
         fmt::Result::Ok(())
     }
 }
 
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut target = self.class.clone();
+        if let Some(id) = &self.id {
+            target += &format!("[{}]", id);
+        }
+        if let Some(key) = &self.key {
+            target += &format!("${}", key);
+        }
+        match &self.aggregate {
+            Some(aggregate) => write!(f, "{}({})", aggregate, target),
+            None => write!(f, "{}", target),
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}${}@{}", self.class, self.key, self.line)
+    }
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Mean => "mean",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Any => "any",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 impl fmt::Display for List {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_items(f, 1)
+    }
+
+}
+
+impl List {
+    // sub-lists are written as the `*` items indented below a `*` alone
+    fn write_items(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
         for item in &self.items {
-            write!(
-                f,
-                "\n  * {item}",
-                item = item,
-            )?;
+            match &item.value {
+                ValueBase::List(list) => {
+                    write!(f, "\n{}*", indent)?;
+                    list.write_items(f, depth + 1)?;
+                }
+                _ => write!(f, "\n{indent}* {item}", indent = indent, item = item)?,
+            }
         }
-        write!(f, "")?;
         fmt::Result::Ok(())
     }
-
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.value)?;
+        // computed values are written as their expressions
+        match &self.provenance {
+            Provenance::Computed(source) => write!(f, "{}", source)?,
+            _ => match &self.literal {
+                Some(literal) => write!(f, "{}", literal)?,
+                None => write!(f, "{}", self.value)?,
+            },
+        }
         match &self.annotation {
             Some(ann) => {
                 let indent_num = match &self.typ {
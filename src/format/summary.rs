@@ -0,0 +1,129 @@
+use crate::format::{*};
+
+use std::collections::BTreeMap;
+
+// (class, key) -> missing value (`NA` or `NA(code)`) -> count
+pub type MissingSummary = BTreeMap<(String, String), BTreeMap<String, usize>>;
+
+impl Entry {
+    pub fn missing_summary(&self) -> MissingSummary {
+        let mut summary = MissingSummary::new();
+        self.count_missing(&mut summary);
+        summary
+    }
+
+    fn count_missing(&self, summary: &mut MissingSummary) {
+        for key in &self.keys {
+            if let Some(value) = self.variables.get(key) {
+                let mut missing: Vec<String> = Vec::new();
+                value.collect_missing(&mut missing);
+                for m in missing {
+                    *summary
+                        .entry((self.class.clone(), key.clone()))
+                        .or_default()
+                        .entry(m)
+                        .or_insert(0) += 1;
+                }
+            }
+        }
+
+        for subentry in &self.subentries {
+            subentry.count_missing(summary);
+        }
+    }
+
+    pub fn to_summary_str(&self) -> Result<String, ReamError> {
+        let mut raw = "class,key,missing,count\n".to_string();
+        for ((class, key), counts) in self.missing_summary() {
+            for (missing, count) in counts {
+                raw += &format!("{},{},{},{}\n", class, key, missing, count);
+            }
+        }
+        Ok(raw)
+    }
+
+    // levels of categorical types with their codes, for value labels and factor levels
+    pub fn to_levels_str(&self) -> Result<String, ReamError> {
+        let mut raw = "levels,code,level\n".to_string();
+        for (name, levels) in self.levels() {
+            for (i, level) in levels.iter().enumerate() {
+                raw += &format!("{},{},{}\n", name, i + 1, level);
+            }
+        }
+        Ok(raw)
+    }
+
+    // the unit of each numeric key, for the codebook
+    pub fn to_units_str(&self) -> Result<String, ReamError> {
+        let mut units = BTreeMap::new();
+        self.collect_units(&mut units);
+        let mut raw = "class,key,unit\n".to_string();
+        for ((class, key), unit) in units {
+            raw += &format!("{},{},{}\n", class, key, unit);
+        }
+        Ok(raw)
+    }
+
+    fn collect_units(&self, units: &mut BTreeMap<(String, String), String>) {
+        for key in &self.keys {
+            if let Some(unit) = self.variables.get(key).and_then(|v| v.unit()) {
+                units.insert((self.class.clone(), key.clone()), unit.clone());
+            }
+        }
+
+        for subentry in &self.subentries {
+            subentry.collect_units(units);
+        }
+    }
+
+    // every referenced value, with the value it was resolved from
+    pub fn to_origins_str(&self) -> Result<String, ReamError> {
+        let mut raw = "class,key,line,origin\n".to_string();
+        self.write_origins(&mut raw);
+        Ok(raw)
+    }
+
+    fn write_origins(&self, raw: &mut String) {
+        for key in &self.keys {
+            if let Some(value) = self.variables.get(key) {
+                let mut origins: Vec<&Origin> = Vec::new();
+                value.collect_origins(&mut origins);
+                for origin in origins {
+                    let line = value.span.start().line();
+                    *raw += &format!("{},{},{},{}\n", self.class, key, line, origin);
+                }
+            }
+        }
+
+        for subentry in &self.subentries {
+            subentry.write_origins(raw);
+        }
+    }
+}
+
+impl Value {
+    // referenced values, including list items
+    fn collect_origins<'a>(&'a self, output: &mut Vec<&'a Origin>) {
+        if let Some(origin) = self.origin() {
+            output.push(origin);
+        }
+        if let ValueBase::List(list) = &self.value {
+            for item in &list.items {
+                item.collect_origins(output);
+            }
+        }
+    }
+
+    // missing values, including list items
+    fn collect_missing(&self, output: &mut Vec<String>) {
+        match &self.value {
+            ValueBase::Na(_) => output.push(self.value.get_raw()),
+            ValueBase::List(list) => {
+                for item in &list.items {
+                    item.collect_missing(output);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::format::{*};
+
+// Expansion of templates, `@ TEMPLATE(name)` and `@ USE(name)`,
+// and of loops, `@ FOR(key, values)`.
+// Generated entries carry none of these decorators.
+impl Entry {
+    pub fn template_name(&self) -> Option<String> {
+        self.decorators.iter().flatten().find_map(|d| match d {
+            Decorator::Template(name) => Some(name.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn template_use(&self) -> Option<String> {
+        self.decorators.iter().flatten().find_map(|d| match d {
+            Decorator::Use(name) => Some(name.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn loop_values(&self) -> Option<(String, Vec<String>)> {
+        self.decorators.iter().flatten().find_map(|d| match d {
+            Decorator::For(key, args) => loop_values(args).map(|values| (key.clone(), values)),
+            _ => None,
+        })
+    }
+
+    // Keys of the template come first, in the order of the template,
+    // unless the instance gives its own value.
+    // Subentries of the template come before those of the instance.
+    pub fn apply_template(&mut self, template: &Entry) {
+        let mut keys = Vec::new();
+        for key in &template.keys {
+            keys.push(key.clone());
+            if self.variables.get(key).is_some() {
+                continue;
+            }
+            if let Some(value) = template.variables.get(key) {
+                self.variables.insert(key.clone(), value.clone());
+            }
+            if template.is_ref_key(key) {
+                self.ref_keys.push(key.clone());
+            }
+            if template.is_optional(key) && !self.is_optional(key) {
+                self.optional_keys.push(key.clone());
+            }
+            if template.is_typed(key) && !self.is_typed(key) {
+                self.typed_keys.push(key.clone());
+            }
+        }
+        for key in &self.keys {
+            if !keys.contains(key) {
+                keys.push(key.clone());
+            }
+        }
+        self.keys = keys;
+
+        // the instance keeps its loop, if any, to be expanded after the template is applied
+        let mut decorators: Vec<Decorator> = template
+            .decorators
+            .iter()
+            .flatten()
+            .filter(|d| !matches!(d, Decorator::Template(_)))
+            .cloned()
+            .collect();
+        decorators.extend(self.decorators.iter().flatten().filter(|d| !matches!(d, Decorator::Use(_))).cloned());
+        self.decorators = if decorators.is_empty() { None } else { Some(decorators) };
+
+        let mut subentries = Vec::new();
+        for subentry in &template.subentries {
+            let mut subentry = subentry.clone();
+            subentry.set_level(self.level + 1);
+            subentry.parent_class = Some(self.class.clone());
+            subentries.push(subentry);
+        }
+        subentries.append(&mut self.subentries);
+        self.subentries = subentries;
+    }
+
+    // A copy of the entry for one value of its loop.
+    // The key comes first, unless the entry already has it.
+    pub fn loop_instance(&self, key: &str, value: Value) -> Entry {
+        let mut entry = self.clone();
+        let decorators: Vec<Decorator> = self
+            .decorators
+            .iter()
+            .flatten()
+            .filter(|d| !d.is_generative())
+            .cloned()
+            .collect();
+        entry.decorators = if decorators.is_empty() { None } else { Some(decorators) };
+        if !entry.keys.iter().any(|k| k == key) {
+            entry.keys.insert(0, key.to_string());
+        }
+        entry.ref_keys.retain(|k| k != key);
+        entry.variables.insert(key.to_string(), value);
+        entry
+    }
+
+    fn set_level(&mut self, level: usize) {
+        self.level = level;
+        for subentry in &mut self.subentries {
+            subentry.set_level(level + 1);
+        }
+    }
+
+    pub fn take_subentries(&mut self) -> Vec<Entry> {
+        std::mem::take(&mut self.subentries)
+    }
+}
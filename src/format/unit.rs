@@ -0,0 +1,99 @@
+use crate::format::{is_num, Decimal, Interval};
+
+// A unit of measure, such as `km2`, `million persons` or `billion USD`:
+// an optional scale word, then an optional base unit.
+// Units of the same dimension convert by their factors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unit {
+    dimension: String,
+    factor: Decimal,
+}
+
+impl Unit {
+    // `None` for unknown words
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace().peekable();
+        let scale = match words.peek().and_then(|w| scale(w)) {
+            Some(s) => {
+                words.next();
+                s
+            }
+            None => "1",
+        };
+        let (dimension, factor) = match (words.next(), words.next()) {
+            (None, _) if scale != "1" => ("count".to_string(), "1"),
+            (Some(base), None) => base_unit(base)?,
+            _ => return None,
+        };
+        Some(Self {
+            dimension,
+            factor: Decimal::parse(scale)?.mul(&Decimal::parse(factor)?)?,
+        })
+    }
+
+    pub fn dimension(&self) -> &str {
+        &self.dimension
+    }
+
+    // `value` in this unit, expressed in `to`, exactly unless the quotient does not terminate,
+    // e.g. `0.7 km` as `700 m`; `None` across dimensions
+    pub fn convert(&self, value: Decimal, to: &Unit) -> Option<Decimal> {
+        if self.dimension != to.dimension {
+            return None;
+        }
+        value.mul(&self.factor)?.div(&to.factor)
+    }
+}
+
+fn scale(word: &str) -> Option<&'static str> {
+    let factor = match word {
+        "thousand" | "thousands" => "1e3",
+        "million" | "millions" => "1e6",
+        "billion" | "billions" => "1e9",
+        _ => return None,
+    };
+    Some(factor)
+}
+
+// dimension and factor to the reference unit of the dimension
+fn base_unit(word: &str) -> Option<(String, &'static str)> {
+    let (dimension, factor) = match word {
+        "persons" | "people" => ("count", "1"),
+        "km2" => ("area", "1"),
+        "m2" => ("area", "1e-6"),
+        "ha" => ("area", "1e-2"),
+        "mi2" => ("area", "2.589988110336"),
+        "km" => ("length", "1"),
+        "m" => ("length", "1e-3"),
+        "mi" => ("length", "1.609344"),
+        "kg" => ("mass", "1"),
+        "t" => ("mass", "1e3"),
+        "lb" => ("mass", "0.45359237"),
+        // currencies, by ISO 4217 code, do not convert
+        code if code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase()) => {
+            return Some((code.to_string(), "1"));
+        }
+        _ => return None,
+    };
+    Some((dimension.to_string(), factor))
+}
+
+// `12.3 million` or `2..3 million` as the number and its unit, if the rest is a known unit
+pub fn split_quantity(value: &str) -> Option<(&str, &str)> {
+    split_number(value).filter(|(_, unit)| Unit::parse(unit).is_some())
+}
+
+// the number or interval a value starts with, and the words after it
+pub fn split_number(value: &str) -> Option<(&str, &str)> {
+    let is_number = |s: &str| is_num(s) || Interval::parse(s).is_some();
+    if is_number(value) {
+        return None;
+    }
+    value.rmatch_indices(' ').find_map(|(i, _)| {
+        let (number, rest) = (value[..i].trim(), value[i..].trim());
+        match is_number(number) && !rest.is_empty() {
+            true => Some((number, rest)),
+            false => None,
+        }
+    })
+}
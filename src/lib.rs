@@ -1,13 +1,20 @@
+pub mod constraint;
 pub mod decorator;
+pub mod document;
 pub mod error;
+pub mod expression;
 pub mod format;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
 
+pub use document::{Document, Edit};
 pub use parser::*;
 pub use scanner::*;
 // pub use ream::*;
 
+use error::ReamError;
+use format::Entry;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
@@ -26,15 +33,7 @@ pub fn ream2ast(source: &str) -> Result<JsValue, JsValue> {
 
     let mut parser = Parser::new(&source);
 
-    let ast: Result<JsValue, JsValue> = match parser.parse_entry() {
-        Err(e) => Err(JsValue::from_serde(&e).unwrap()),
-        Ok(opt_entry) => match opt_entry {
-            None => Ok(JsValue::NULL),
-            Some(entry) => Ok(JsValue::from_serde(&entry).unwrap()),
-        },
-    };
-
-    ast
+    ast(parser.parse_entry().as_ref().map(Option::as_ref))
 }
 
 #[wasm_bindgen]
@@ -45,16 +44,75 @@ pub fn ream2csv(source: &str) -> JsValue {
 
     let mut parser = Parser::new(&source);
 
-    let result = match parser.parse_entry() {
-        Err(e) => JsValue::from_serde(&e).unwrap(),
-        Ok(opt_entry) => match opt_entry {
-            None => JsValue::NULL,
-            Some(entry) => {
-                let list = entry.to_csv_list().unwrap(); // TODO: unwrap?
-                JsValue::from_serde(&list).unwrap()
-            }
+    csv(parser.parse_entry().as_ref().map(Option::as_ref))
+}
+
+// the entry as an object, or the error
+fn ast(parsed: Result<Option<&Entry>, &ReamError>) -> Result<JsValue, JsValue> {
+    match parsed {
+        Err(e) => Err(to_js(e)?),
+        Ok(None) => Ok(JsValue::NULL),
+        Ok(Some(entry)) => to_js(entry),
+    }
+}
+
+// the rows of the entry, or the error, as a value
+fn csv(parsed: Result<Option<&Entry>, &ReamError>) -> JsValue {
+    let result = match parsed {
+        Err(e) => to_js(e),
+        Ok(None) => Ok(JsValue::NULL),
+        Ok(Some(entry)) => match entry.to_csv_list() {
+            Ok(list) => to_js(&list),
+            Err(e) => to_js(&e),
         },
     };
+    result.unwrap_or_else(|e| e)
+}
+
+// `JsValue::from_serde` is deprecated, but kept in this one place
+// until the bindings move to `serde-wasm-bindgen`
+#[allow(deprecated)]
+fn to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue> {
+    JsValue::from_serde(value).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+// A buffer kept between keystrokes, re-parsed incrementally.
+#[wasm_bindgen]
+pub struct ReamDocument {
+    document: Document,
+}
+
+#[wasm_bindgen]
+impl ReamDocument {
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str) -> Self {
+        Self {
+            document: Document::new(source),
+        }
+    }
+
+    // replace the bytes `start..end` of the source with `text`;
+    // errors in the edited source are returned, as by `ast`
+    pub fn edit(&mut self, start: usize, end: usize, text: &str) -> Result<(), JsValue> {
+        let source = self.document.source();
+        if start > end || end > source.len() || !source.is_char_boundary(start) || !source.is_char_boundary(end) {
+            return Err(JsValue::from_str("invalid range"));
+        }
+        let edit = Edit {
+            range: start..end,
+            text: text.to_string(),
+        };
+        match self.document.edit(&edit) {
+            Err(e) => Err(to_js(e)?),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    pub fn ast(&self) -> Result<JsValue, JsValue> {
+        ast(self.document.entry())
+    }
 
-    result
+    pub fn csv(&self) -> JsValue {
+        csv(self.document.entry())
+    }
 }
@@ -1,8 +1,11 @@
 mod error;
+mod expression;
 mod parser;
+mod resolver;
 mod format;
 mod scanner;
 mod decorator;
+mod constraint;
 
 use crate::parser::*;
 use crate::scanner::*;
@@ -30,7 +33,7 @@ fn main() {
                 .long("format")
                 .short('f')
                 .takes_value(true)
-                .possible_values(&["AST", "CSV"]),
+                .possible_values(&["AST", "CSV", "SUMMARY", "ORIGINS", "LEVELS", "UNITS"]),
         )
         .arg(
             Arg::new("output")
@@ -38,6 +41,60 @@ fn main() {
                 .short('o')
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("na")
+                .long("na")
+                .value_name("LITERAL")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("canonical-numbers")
+                .long("canonical-numbers")
+                .required(false)
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("missing")
+                .long("missing")
+                .value_name("TOKEN")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("missing-style")
+                .long("missing-style")
+                .takes_value(true)
+                .possible_values(["token", "label", "stata", "spss"]),
+        )
+        .arg(
+            Arg::new("categories")
+                .long("categories")
+                .takes_value(true)
+                .possible_values(["label", "code"]),
+        )
+        .arg(
+            Arg::new("literals")
+                .long("literals")
+                .takes_value(true)
+                .possible_values(["default", "en", "de", "fr", "nl"]),
+        )
+        .arg(
+            Arg::new("intervals")
+                .long("intervals")
+                .takes_value(true)
+                .possible_values(["bounds", "raw"]),
+        )
+        .arg(
+            Arg::new("annotations")
+                .long("annotations")
+                .required(false)
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("join")
+                .long("join")
+                .required(false)
+                .takes_value(false),
+        )
         .arg(
             Arg::new("print")
                 .long("print")
@@ -71,8 +128,38 @@ fn main() {
 
         let print = matches.is_present("print");
 
+        let mut parser_options = ParserOptions::default();
+        if let Some(na) = matches.value_of("na") {
+            parser_options.na = na.to_string();
+        }
+        parser_options.canonical_numbers = matches.is_present("canonical-numbers");
+        if let Some(profile) = matches.value_of("literals").and_then(LiteralProfile::named) {
+            parser_options.literals = profile;
+        }
+
+        let mut csv_options = CsvOptions::default();
+        if let Some(missing) = matches.value_of("missing") {
+            csv_options.missing = missing.to_string();
+        }
+        csv_options.missing_style = match matches.value_of("missing-style") {
+            Some("label") => MissingStyle::Label,
+            Some("stata") => MissingStyle::Stata,
+            Some("spss") => MissingStyle::Spss,
+            _ => MissingStyle::Token,
+        };
+        csv_options.categories = match matches.value_of("categories") {
+            Some("code") => CategoryStyle::Code,
+            _ => CategoryStyle::Label,
+        };
+        csv_options.intervals = match matches.value_of("intervals") {
+            Some("raw") => IntervalStyle::Raw,
+            _ => IntervalStyle::Bounds,
+        };
+        csv_options.join = matches.is_present("join");
+        csv_options.annotations = matches.is_present("annotations");
+
         let file = fs::read_to_string(input_path).unwrap();
-        let mut parser = Parser::new(&file);
+        let mut parser = Parser::with_options(&file, parser_options);
 
         let output_text = match matches.value_of("format") {
             Some(f) => {
@@ -80,12 +167,19 @@ fn main() {
                     Ok(Some(mut e)) => {
                         match f {
                             "AST" => e.to_ast_str().unwrap(),
-                            "CSV" => e.to_csv_str().unwrap(),
+                            "CSV" => match e.to_csv_str_with(&csv_options) {
+                                Ok(s) => s,
+                                Err(e) => format!("{}", e),
+                            },
+                            "SUMMARY" => e.to_summary_str().unwrap(),
+                            "ORIGINS" => e.to_origins_str().unwrap(),
+                            "LEVELS" => e.to_levels_str().unwrap(),
+                            "UNITS" => e.to_units_str().unwrap(),
                             "RAW" => e.to_string(),
                             _ => panic!("output format not supported"),
                         }
                     },
-                    Err(e) => format!("{:?}", e),
+                    Err(e) => format!("{}", e),
                     Ok(None) => panic!("None"),
 
                 }
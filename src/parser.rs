@@ -1,44 +1,76 @@
 use crate::error::*;
+use crate::expression::Expression;
 use crate::format::*;
 use crate::scanner::*;
+use crate::constraint::validate;
 use crate::decorator::Decorator;
+use crate::resolver::resolve;
 
 use std::collections::{BTreeMap, HashMap, VecDeque};
 
+#[derive(Debug, Clone)]
+pub struct ParserOptions {
+    pub na: String, // literal for missing values
+    pub canonical_numbers: bool, // write numbers as `7.5` for `+007.50`
+    pub literals: LiteralProfile, // spellings of booleans and numbers
+    pub types: TypeRegistry, // custom types, declared by name like built-in types
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            na: "NA".to_string(),
+            canonical_numbers: false,
+            literals: LiteralProfile::default(),
+            types: TypeRegistry::new(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Parser<'source> {
     pub scanner: Scanner<'source>,
+    pub options: ParserOptions,
     pub current_level: usize,
     pub class_history: Vec<String>,
     pub schemas: HashMap<String, EntrySchema>,
 
-    upstream: HashMap<String, VariableMap>,
-    downstream: HashMap<String, Vec<VariableMap>>,
-    parse_direction: Direction,
     ref_keys_buffer: Vec<String>,
+    missing_codes: Vec<String>,
+    levels: HashMap<String, Vec<String>>, // declared by `@ LEVELS(...)`
+    templates: HashMap<String, Entry>,
+    deferred: usize, // depth of templates and loops being parsed
+    defaults: Vec<(usize, Decorator)>, // `@ DEFAULT(...)` in scope, with the level of their entry
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum Direction {
-    Down,
-    Up,
-} 
-
 impl<'source> Parser<'source> {
     pub fn new(source: &'source str) -> Self {
+        Self::with_options(source, ParserOptions::default())
+    }
+
+    pub fn with_options(source: &'source str, options: ParserOptions) -> Self {
         Parser {
-            scanner: Scanner::new(source),
+            scanner: Scanner::with_types(source, options.types.clone()),
+            options,
             current_level: 0,
             class_history: vec!["_root_".to_string()],
             schemas: HashMap::new(),
 
-            upstream: HashMap::new(),
-            downstream: HashMap::new(),
-            parse_direction: Direction::Down,
             ref_keys_buffer: Vec::new(),
+            missing_codes: Vec::new(),
+            levels: HashMap::new(),
+            templates: HashMap::new(),
+            deferred: 0,
+            defaults: Vec::new(),
         }
     }
 
+    // the source starts at line `line` of a larger document, whose spans it keeps
+    pub fn starting_at(mut self, line: usize) -> Self {
+        self.scanner.loc = Marker::new(line, 0);
+        self
+    }
+
     pub fn push_ref_key(&mut self, key: String) {
         self.ref_keys_buffer.push(key);
     }
@@ -54,7 +86,7 @@ impl<'source> Parser<'source> {
         let level = self.current_level;
         match level {
             1 => None,                                        // root node
-            _ => Some(self.class_history[level - 2].clone()), // TODO: clone!
+            _ => Some(self.class_history[level - 1].clone()), // TODO: clone!
         }
     }
 
@@ -73,13 +105,13 @@ impl<'source> Parser<'source> {
     //     }
     // }
 
-    pub fn parse_header(&mut self) -> Result<usize, ReamError> {
-        let level = match self.scanner.take_token()? {
-            Some(Token(TokenType::Header(n), _, _)) => n,
+    pub fn parse_header(&mut self) -> Result<(usize, Marker), ReamError> {
+        let (level, position) = match self.scanner.take_token()? {
+            Some(Token(TokenType::Header(n), start, _)) => (n, start),
             _ => return Err(ReamError::ParseError(ParseErrorType::MissingHeaderLevel)),
         };
 
-        Ok(level)
+        Ok((level, position))
     }
 
     pub fn parse_identifier(&mut self) -> Result<String, ReamError> {
@@ -118,14 +150,76 @@ impl<'source> Parser<'source> {
 
 
     pub fn parse_entry(&mut self) -> Result<Option<Entry>, ReamError> {
+        let mut entry = match self.parse_tree()? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        // resolve references once the whole tree is known, then check constraints on the values
+        resolve(&mut entry)?;
+        validate(&entry)?;
+
+        Ok(Some(entry))
+    }
+
+    // the tree before references are resolved
+    pub fn parse_tree(&mut self) -> Result<Option<Entry>, ReamError> {
+        let mut entry = match self.parse_entry_inner()? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        // align entries parsed before their schema was complete
+        entry.fill_missing(&self.schemas);
+        entry.assign_ids(&self.schemas);
+
+        Ok(Some(entry))
+    }
+
+    pub fn parse_entry_inner(&mut self) -> Result<Option<Entry>, ReamError> {
 
         // find decorators
         let decorators = self.parse_decorators()?;
 
         // find entry level
-        let level = self.parse_header()?;
+        let (level, position) = self.parse_header()?;
         self.current_level = level;
 
+        // dataset-level decorators
+        if let Some(ds) = &decorators {
+            for decorator in ds.iter().filter(|d| d.is_dataset_level()) {
+                if level != 1 {
+                    return Err(ReamError::DecoratorError(DecoratorErrorType::MisplacedDecorator));
+                }
+                match decorator {
+                    Decorator::Missing(codes) => self.missing_codes = codes.clone(),
+                    Decorator::Levels(name, levels) => {
+                        self.levels.insert(name.clone(), levels.clone());
+                    }
+                    Decorator::Literals(name) => {
+                        self.options.literals = LiteralProfile::named(name).unwrap_or_default();
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // templates and loops are expanded before schema checking
+        let deferred = decorators.iter().flatten().any(|d| matches!(d, Decorator::Template(_) | Decorator::For(_, _)));
+        if deferred {
+            if level == 1 {
+                return Err(ReamError::DecoratorError(DecoratorErrorType::MisplacedDecorator));
+            }
+            self.deferred += 1;
+        }
+
+        // defaults apply to the entry and its subentries
+        for decorator in decorators.iter().flatten() {
+            if let Decorator::Default(_, _, _) = decorator {
+                self.defaults.push((level, decorator.clone()));
+            }
+        }
+
         // find entry class
         let class = self.parse_identifier()?;
         self.push_class(class.clone()); // TODO: clone!
@@ -134,41 +228,67 @@ impl<'source> Parser<'source> {
         let parent_class = self.parent_class();
 
         // init entry
-        let mut entry = Entry::new(class, level, parent_class, decorators);
+        let mut entry = Entry::new(class, level, parent_class, position, decorators);
+
+        // entry-level annotation, one line for each `>`
+        let mut lines = Vec::new();
+        while let Some(line) = self.parse_annotation()? {
+            lines.push(line);
+        }
+        if !lines.is_empty() {
+            entry.set_annotation(Some(lines.join("\n")));
+        }
 
         // loop for variables
         while let Some(Token(TokenType::Dash, _, _)) = self.scanner.peek_token()? {
             self.scanner.take_token()?; // consume Dash
-            let (key, val) = self.parse_variable()?;
+            let (key, val, optional, typed) = self.parse_variable()?;
             entry.push_key(key.clone());
+            if optional {
+                entry.push_optional_key(key.clone());
+            }
+            if typed {
+                entry.push_typed_key(key.clone());
+            }
             entry.insert_variable(key, val)?;
         }
 
-        // check schema
-        let mut entry = self.check_schema(entry)?;
-
-        // update upstream
-        self.upstream.insert(entry.class(), entry.variable_map());
-
         // move unresolved ref keys from parser to entry
         entry.set_ref_key(self.ref_keys_buffer.clone()); // TODO: clone!
         self.ref_keys_buffer = Vec::new();
 
+        // instance of a template
+        if let Some(name) = entry.template_use() {
+            match self.templates.get(&name) {
+                Some(template) => entry.apply_template(template),
+                None => return Err(ReamError::DecoratorError(DecoratorErrorType::TemplateNotFound(name))),
+            }
+        }
+
+        // defaults of the class, the innermost first
+        self.inherit_defaults(&mut entry, position)?;
+
+        // check schema
+        let mut entry = match self.deferred {
+            0 => self.check_schema_tree(entry)?,
+            _ => entry,
+        };
+
         // loop for subentries
         while let Some(Token(TokenType::Header(next_level), _, _))
             | Some(Token(TokenType::At(next_level), _, _)) = self.scanner.peek_token()? {
             if next_level.to_owned() == self.current_level + 1 {
                 // child entry
-                self.parse_direction = Direction::Down;
-                let subentry = match self.parse_entry()? {
+                let subentry = match self.parse_entry_inner()? {
                     Some(sub) => sub,
                     None => return Err(ReamError::ParseError(ParseErrorType::MissingSubentry)),
                 };
                 // let subentry = self.parse_entry()?;
-                entry.push_subentry(subentry);
+                for subentry in self.expand(subentry)? {
+                    entry.push_subentry(subentry);
+                }
             } else if next_level.to_owned() <= self.current_level {
                 // return to parent entry
-                self.parse_direction = Direction::Up;
                 self.current_level -= 1;
                 break;
             } else {
@@ -177,48 +297,106 @@ impl<'source> Parser<'source> {
             }
         }
 
-        // downstream reference
-        entry.resolve_downstream_ref(&self.downstream)?;
-
-        // cleanup
-
         // pop current class
         self.pop_class();
+        self.defaults.retain(|(l, _)| *l < level);
 
-        // move current entry from upstream to downstream
-        let variable_map = match self.upstream.get(&entry.class()) {
-            Some(map) => map.clone(),
-            None => return Err(ReamError::Placeholder),
-        };
-        self.upstream.remove(&entry.class());
-        self.insert_downstream(entry.class().clone(), variable_map.clone());
-
+        if deferred {
+            self.deferred -= 1;
+        }
 
         Ok(Some(entry))
     }
 
-    pub fn insert_downstream(&mut self, class: String, variable_map: VariableMap) {
-        if let Some(x) = self.downstream.get_mut(&class) {
-            x.push(variable_map);
-        } else {
-            self.downstream.insert(class.clone(), vec![variable_map]);
+    pub fn inherit_defaults(&mut self, entry: &mut Entry, position: Marker) -> Result<(), ReamError> {
+        // the innermost default of each key, in the order of declaration
+        let mut defaults: Vec<(&String, &String)> = Vec::new();
+        for (_, decorator) in self.defaults.iter().rev() {
+            if let Decorator::Default(class, key, v) = decorator {
+                if *class == entry.class() && !defaults.iter().any(|(k, _)| *k == key) {
+                    defaults.insert(0, (key, v));
+                }
+            }
         }
+
+        let span = Span::new(position, position);
+        for (key, v) in defaults {
+            let (value_base, typ) = match self.is_missing(v) {
+                true => (self.parse_missing(v)?, ValueType::Unknown),
+                false => ValueBase::new(v.clone(), ValueType::Unknown)?,
+            };
+            let value = Value::new(value_base, None, typ, span);
+            entry.inherit(key.clone(), value, self.schemas.get(&entry.class()));
+        }
+        Ok(())
+    }
+
+    // Templates are kept aside, and loops give one entry for each value.
+    pub fn expand(&mut self, entry: Entry) -> Result<Vec<Entry>, ReamError> {
+        if let Some(name) = entry.template_name() {
+            self.templates.insert(name, entry);
+            return Ok(Vec::new());
+        }
+
+        let (key, values) = match entry.loop_values() {
+            Some(l) => l,
+            None => return Ok(vec![entry]),
+        };
+        let span = Span::new(entry.position(), entry.position());
+        let mut entries = Vec::new();
+        for v in values {
+            // read like a written value, e.g. `NA`
+            let value = self.parse_scalar(&key, v, ValueType::Unknown, None, span)?;
+            let instance = entry.loop_instance(&key, value);
+            entries.push(match self.deferred {
+                0 => self.check_schema_tree(instance)?,
+                _ => instance,
+            });
+        }
+        Ok(entries)
+    }
+
+    // check an entry and the subentries it already has
+    pub fn check_schema_tree(&mut self, mut entry: Entry) -> Result<Entry, ReamError> {
+        let subentries = entry.take_subentries();
+        let mut entry = self.check_schema(entry)?;
+        entry.fill_missing(&self.schemas);
+        for subentry in subentries {
+            let subentry = self.check_schema_tree(subentry)?;
+            entry.push_subentry(subentry);
+        }
+        Ok(entry)
     }
 
     pub fn check_schema(&mut self, entry: Entry) -> Result<Entry, ReamError> {
-        if self.schemas.contains_key(&entry.class()) {
+        let entry = if self.schemas.contains_key(&entry.class()) {
             // schema exist -> check
-            self.check_schema_inner(entry)
+            self.check_schema_inner(entry)?
         } else {
             // schema does not exist -> init
-            self.init_schema(entry)
+            self.init_schema(entry)?
+        };
+
+        // identifier key and foreign keys must be keys of the class
+        if let Some(schema) = self.schemas.get(&entry.class()) {
+            if !schema.permits_parent(&entry.get_parent_class()) {
+                return Err(ReamError::SchemaError(SchemaErrorType::IncorrectParentClass));
+            }
+            if let Some(key) = schema.id_key() {
+                if schema.field(key).is_none() {
+                    return Err(ReamError::SchemaError(SchemaErrorType::IncorrectIdKey));
+                }
+            }
+            if schema.foreign_keys().iter().any(|fk| schema.field(&fk.key).is_none()) {
+                return Err(ReamError::SchemaError(SchemaErrorType::IncorrectForeignKey));
+            }
         }
+
+        Ok(entry)
     }
 
     pub fn init_schema(&mut self, entry: Entry) -> Result<Entry, ReamError> {
-        let entry_keys = entry.keys();
-        let entry_parent_class = entry.get_parent_class();
-        let entry_schema = EntrySchema::new(entry_keys, entry_parent_class);
+        let entry_schema = entry.get_schema();
 
         let entry_class = entry.class().clone(); // TODO: clone!
         self.schemas.insert(entry_class, entry_schema);
@@ -226,94 +404,311 @@ impl<'source> Parser<'source> {
         Ok(entry)
     }
 
-    pub fn check_schema_inner(&self, entry: Entry) -> Result<Entry, ReamError> {
+    pub fn check_schema_inner(&mut self, entry: Entry) -> Result<Entry, ReamError> {
         let entry_schema = entry.get_schema();
-        let parser_schema = self.get_schema(entry.class())?;
+        let class = entry.class();
+        let parser_schema = match self.schemas.get_mut(&class) {
+            Some(schema) => schema,
+            None => return Err(ReamError::SchemaError(SchemaErrorType::IncorrectSchema)),
+        };
 
-        if entry_schema == parser_schema {
-            Ok(entry)
-        } else {
-            Err(ReamError::SchemaError(SchemaErrorType::IncorrectKeys))
-        }
+        parser_schema.merge(&class, &entry_schema)?;
+
+        Ok(entry)
     }
 
-    pub fn parse_variable(&mut self) -> Result<(String, Value), ReamError> {
+    // the key, the value, and whether the key is optional and declared with a type
+    pub fn parse_variable(&mut self) -> Result<(String, Value, bool, bool), ReamError> {
         let key = self.parse_identifier()?;
+        // optional keys are marked by a trailing `?`
+        let (key, optional) = match key.strip_suffix('?') {
+            Some(k) => (k.to_string(), true),
+            None => (key, false),
+        };
         let typ = self.parse_type()?;
-        self.parse_colon()?;
-        let value = self.parse_value(&key, typ)?;
+        let typed = typ != ValueType::Unknown;
+        let unit = self.parse_unit()?;
+        let value = match self.scanner.peek_token()? {
+            Some(Token(TokenType::Equals, _, _)) => {
+                self.scanner.take_token()?;
+                self.parse_expression(&key, typ)?.with_unit(unit)
+            }
+            _ => {
+                self.parse_colon()?;
+                self.parse_value(&key, typ, unit.as_deref())?
+            }
+        };
 
-        Ok((key, value))
+        Ok((key, value, optional, typed))
     }
 
-    pub fn parse_value(&mut self, key: &String, typ: ValueType) -> Result<Value, ReamError> {
+    pub fn parse_value(&mut self, key: &String, typ: ValueType, unit: Option<&str>) -> Result<Value, ReamError> {
         let tok_value = self.scanner.take_token()?;
-        let (value_base, typ) = match tok_value {
-            Some(Token(TokenType::Value(v), _, _)) => {
-                match typ {
-                    // if value is a reference, get the reference
-                    ValueType::Ref => {
-                        let (value_base, typ) = self.get_ref(v)?;
-                        match value_base {
-                            // unresolved reference will be pushed to ref_key_buffer
-                            // and checked for downstream reference
-                            ValueBase::Ref(c, k) => {
-                                self.push_ref_key(key.clone());
-                                (ValueBase::new_ref(c, k), typ)
-                            },
-                            _ => (value_base, typ),
-                        }
-                    },
-                    _ => ValueBase::new(v, typ)?,
-                }
+        let value = match tok_value {
+            Some(Token(TokenType::Value(v), start, end)) => {
+                self.parse_scalar(key, v, typ, unit, Span::new(start, end))?
+            }
+            Some(Token(TokenType::Star, start, _)) => {
+                let (value_base, typ, end) = self.parse_list_items(&key, typ, unit, start.col())?;
+                Value::new(value_base, None, typ, Span::new(start, end)).with_unit(unit.map(String::from))
             }
-            Some(Token(TokenType::Star, _, _)) => self.parse_list_items(&key, typ)?,
             _ => return Err(ReamError::ParseError(ParseErrorType::MissingValue)),
         };
 
         let annotation = self.parse_annotation()?;
 
-        let value = Value::new(value_base, annotation, typ);
+        Ok(value.with_annotation(annotation))
+    }
 
-        Ok(value)
+    // the value of a single line, with its unit if any
+    pub fn parse_scalar(
+        &mut self,
+        key: &String,
+        mut v: String,
+        typ: ValueType,
+        unit: Option<&str>,
+        span: Span,
+    ) -> Result<Value, ReamError> {
+        // booleans and numbers in the literal profile, e.g. `ja` or `0,59`
+        let literal = match typ {
+            ValueType::Unknown | ValueType::Bool | ValueType::Num | ValueType::Int if !is_bracketed(&v) => {
+                self.options.literals.normalize(&v).map(|normal| std::mem::replace(&mut v, normal))
+            }
+            _ => None,
+        };
+        let mut value_unit = unit.map(String::from);
+        let (value_base, typ) = match (typ, unit) {
+            // missing value is valid for any type
+            (typ, _) if self.is_missing(&v) => (self.parse_missing(&v)?, typ),
+            // references are resolved after the whole tree is parsed
+            (typ @ ValueType::Ref(_), _) => {
+                let value_base = self.parse_ref(key, v, span)?;
+                self.push_ref_key(key.clone());
+                (value_base, typ)
+            },
+            (ValueType::Cat(name), _) => {
+                let value_base = self.parse_level(&name, v, span)?;
+                (value_base, ValueType::Cat(name))
+            },
+            (ValueType::Custom(name), _) => {
+                let value_base = self.parse_custom(&name, v, span)?;
+                (value_base, ValueType::Custom(name))
+            },
+            (ValueType::List(t), _) if is_bracketed(&v) => self.parse_bracketed(key, &v, *t, unit, span)?,
+            (expected @ ValueType::List(_), _) => {
+                return Err(ReamError::TypeError(TypeErrorType::NotAList(Box::new(ListMismatch {
+                    expected,
+                    value: v,
+                    span,
+                }))));
+            }
+            (typ, Some(unit)) => self.parse_quantity(v, typ, unit, span)?,
+            // a number with its unit, e.g. `12.3 million`
+            (typ, None) if typ.is_numeric() || typ == ValueType::Unknown => match split_quantity(&v) {
+                Some((number, written)) => {
+                    value_unit = Some(written.to_string());
+                    ValueBase::new(number.to_string(), typ)?
+                }
+                None => ValueBase::new(v, typ)?,
+            },
+            (typ, None) => ValueBase::new(v, typ)?,
+        };
+        let (value_base, literal) = match value_base {
+            ValueBase::Num(n) if self.options.canonical_numbers => (ValueBase::Num(n.canonical()), None),
+            value_base => (value_base, literal),
+        };
+        Ok(Value::new(value_base, None, typ, span).with_unit(value_unit).with_literal(literal))
     }
 
-    pub fn get_ref(&self, value: String) -> Result<(ValueBase, ValueType), ReamError> {
-        let v: Vec<&str> = value.split('$').collect();
+    // a bracketed list, `[1, 2]` or `[[1, 2], [3]]`, of items of type `typ`
+    pub fn parse_bracketed(
+        &mut self,
+        key: &String,
+        value: &str,
+        typ: ValueType,
+        unit: Option<&str>,
+        span: Span,
+    ) -> Result<(ValueBase, ValueType), ReamError> {
+        let inner = &value[1..value.len() - 1];
+        if inner.trim().is_empty() {
+            return Err(ReamError::ParseError(ParseErrorType::MissingValue));
+        }
+        let mut items = Vec::new();
+        for item in split_items(inner) {
+            let item_typ = match typ {
+                ValueType::Unknown if is_bracketed(item) => ValueType::List(Box::new(ValueType::Unknown)),
+                _ => typ.clone(),
+            };
+            items.push(self.parse_scalar(key, item.to_string(), item_typ, unit, span)?);
+        }
+        let (value_base, item_typ) = List::set_list(items)?;
+        Ok((value_base, ValueType::List(Box::new(item_typ))))
+    }
 
-        if let [class, key] = &v[..] {
-            match self.upstream.get(*class) {
-                Some(variable_map) => match variable_map.get(&key.to_string()) {
-                    Some(s) => Ok(s.get_base_and_typ()),
-                    None => Err(ReamError::ReferenceError(
-                        ReferenceErrorType::VariableKeyNotFound,
-                    )),
-                },
-                None => match self.parse_direction {
-                    Direction::Down => Ok((
-                            ValueBase::new_ref(
-                                (*class).to_string(),
-                                (*key).to_string(),
-                            ),
-                            ValueType::Ref,
-                    )),
-                    Direction::Up => Err(ReamError::ReferenceError(
-                        ReferenceErrorType::EntryClassNotFound,
-                    )),
+    // a number in the declared unit, converted from the unit written after it, if any
+    pub fn parse_quantity(
+        &self,
+        value: String,
+        typ: ValueType,
+        unit: &str,
+        span: Span,
+    ) -> Result<(ValueBase, ValueType), ReamError> {
+        let declared = match Unit::parse(unit) {
+            Some(u) => u,
+            None => return Err(ReamError::TypeError(TypeErrorType::UnknownUnit(unit.to_string()))),
+        };
+        let (number, written) = match split_number(&value) {
+            Some((number, written)) => (number.to_string(), written.to_string()),
+            None => return ValueBase::new(value, typ),
+        };
+        if written == unit {
+            return ValueBase::new(number, typ);
+        }
+        let from = match Unit::parse(&written) {
+            Some(u) => u,
+            None => return Err(ReamError::TypeError(TypeErrorType::UnknownUnit(written))),
+        };
+        if from.dimension() != declared.dimension() {
+            return Err(ReamError::TypeError(TypeErrorType::IncompatibleUnit(Box::new(UnitMismatch {
+                expected: unit.to_string(),
+                found: written,
+                span,
+            }))));
+        }
+        let convert = |d: Decimal| from.convert(d, &declared);
+        // `11.4 million` may be a whole number of persons; numbers out of range are invalid
+        let (value_base, integral) = match ValueBase::new(number, ValueType::Num)? {
+            (ValueBase::Num(n), _) => match convert(n.decimal()) {
+                Some(converted) => (ValueBase::Num(Number::from_decimal(converted)), converted.as_i64().is_some()),
+                None => return Err(ReamError::TypeError(TypeErrorType::InvalidNumber)),
+            },
+            (ValueBase::Interval(interval), _) => match interval.map(convert) {
+                Some(converted) => {
+                    let integral = converted.is_integral();
+                    (ValueBase::Interval(Box::new(converted)), integral)
                 }
+                None => return Err(ReamError::TypeError(TypeErrorType::InvalidNumber)),
+            },
+            _ => return Err(ReamError::TypeError(TypeErrorType::InvalidNumber)),
+        };
+        if typ == ValueType::Int && !integral {
+            return Err(ReamError::TypeError(TypeErrorType::InvalidInteger));
+        }
+        Ok((value_base, typ))
+    }
+
+    // computed variables are evaluated with references, after the whole tree is parsed
+    pub fn parse_expression(&mut self, key: &String, typ: ValueType) -> Result<Value, ReamError> {
+        let (v, span) = match self.scanner.take_token()? {
+            Some(Token(TokenType::Value(v), start, end)) => (v, Span::new(start, end)),
+            _ => return Err(ReamError::ParseError(ParseErrorType::MissingValue)),
+        };
+        let expression = match Expression::parse(&v) {
+            Some(expression) => expression,
+            None => {
+                let site = ReferenceSite {
+                    class: self.class_history.last().cloned().unwrap_or_default(),
+                    key: key.to_string(),
+                    reference: v,
+                    span,
+                };
+                return Err(ReamError::ExpressionError(ExpressionErrorType::InvalidExpression(site)));
             }
-        } else {
-            return Err(ReamError::ReferenceError(
-                ReferenceErrorType::InvalidReference,
-            ));
+        };
+        self.push_ref_key(key.clone());
+
+        let annotation = self.parse_annotation()?;
+
+        Ok(Value::new(ValueBase::Expr(Box::new(expression)), annotation, typ, span))
+    }
+
+    pub fn parse_ref(&self, key: &str, value: String, span: Span) -> Result<ValueBase, ReamError> {
+        match Reference::parse(&value) {
+            Some(reference) => Ok(ValueBase::new_ref(reference)),
+            None => {
+                let site = ReferenceSite {
+                    class: self.class_history.last().cloned().unwrap_or_default(),
+                    key: key.to_string(),
+                    reference: value,
+                    span,
+                };
+                Err(ReamError::ReferenceError(ReferenceErrorType::InvalidReference(site)))
+            }
+        }
+    }
+
+    // `NA`, or `NA(code)` with a declared code;
+    // other values such as `NA(North America)` are ordinary values
+    pub fn is_missing(&self, value: &str) -> bool {
+        match value.strip_prefix(self.options.na.as_str()) {
+            Some("") => true,
+            Some(rest) => rest
+                .strip_prefix('(')
+                .and_then(|r| r.strip_suffix(')'))
+                .is_some_and(|code| self.missing_codes.iter().any(|c| c == code.trim())),
+            None => false,
+        }
+    }
+
+    // `NA`, or `NA(code)` with a code declared by `@ MISSING(...)`
+    pub fn parse_missing(&self, value: &str) -> Result<ValueBase, ReamError> {
+        let rest = &value[self.options.na.len()..];
+        if rest.is_empty() {
+            return Ok(ValueBase::Na(None));
+        }
+
+        match rest.strip_prefix('(').and_then(|r| r.strip_suffix(')')) {
+            Some(code) if self.missing_codes.iter().any(|c| c == code.trim()) => {
+                Ok(ValueBase::Na(Some(code.trim().to_string())))
+            }
+            _ => Err(ReamError::TypeError(TypeErrorType::UnknownMissingCode)),
+        }
+    }
+
+    // one of the levels declared by `@ LEVELS(name, ...)`
+    pub fn parse_level(&self, name: &str, value: String, span: Span) -> Result<ValueBase, ReamError> {
+        let levels = match self.levels.get(name) {
+            Some(levels) => levels,
+            None => return Err(ReamError::TypeError(TypeErrorType::UndeclaredLevels(name.to_string()))),
+        };
+        if levels.contains(&value) {
+            return Ok(ValueBase::Str(value));
+        }
+        let nearest = levels.iter().min_by_key(|level| edit_distance(level, &value)).cloned();
+        Err(ReamError::TypeError(TypeErrorType::UnknownLevel(Box::new(LevelMismatch {
+            levels: name.to_string(),
+            value,
+            nearest,
+            span,
+        }))))
+    }
+
+    // a value of a custom type, in canonical form, stored as its export type
+    pub fn parse_custom(&self, name: &str, value: String, span: Span) -> Result<ValueBase, ReamError> {
+        let typ = match self.options.types.get(name) {
+            Some(typ) => typ,
+            None => return Err(ReamError::TypeError(TypeErrorType::UnknownType)),
+        };
+        if !typ.validate(&value) {
+            return Err(ReamError::TypeError(TypeErrorType::InvalidCustomValue(Box::new(CustomMismatch {
+                typ: name.to_string(),
+                value,
+                span,
+            }))));
         }
+        let (value_base, _) = ValueBase::new(typ.canonicalize(&value), typ.export_type())?;
+        Ok(value_base)
     }
 
+    // `*` items at column `col`; an item may be a sub-list,
+    // bracketed or of the `*` items indented below a `*` alone
     pub fn parse_list_items(
         &mut self,
         key: &String,
         typ: ValueType,
-    ) -> Result<(ValueBase, ValueType), ReamError> {
+        unit: Option<&str>,
+        col: usize,
+    ) -> Result<(ValueBase, ValueType, Marker), ReamError> {
         // unwrap list type
         let typ = match typ {
             ValueType::List(t) => *t,
@@ -321,33 +716,84 @@ impl<'source> Parser<'source> {
             _ => return Err(ReamError::TypeError(TypeErrorType::UnknownType)),
         };
 
-        // parse first item
-        let first_item = self.parse_value(&key, typ.clone())?;
-
-        // init list
-        let item_typ = first_item.typ().clone(); // get the updated type
-        let mut list = List::new(item_typ.clone(), first_item);
-
-        // loop through list items
+        // parse items, with the text of those inferred as sub-lists
+        let mut items = Vec::new();
+        let mut inferred = Vec::new();
         loop {
+            let (item_typ, written) = self.item_type(&typ)?;
+            items.push(self.parse_value(key, item_typ, unit)?);
+            inferred.push(written);
             match self.scanner.peek_token()? {
-                Some(Token(TokenType::Star, _, _)) => {
+                Some(Token(TokenType::Star, start, _)) if start.col() == col => {
                     self.scanner.take_token()?; // consume star
-                    let new_item = self.parse_value(&key, typ.clone())?;
-                    // check new item type
-                    if new_item.typ() == list.item_type() {
-                        list.push_item(new_item);
-                    } else {
-                        return Err(ReamError::TypeError(TypeErrorType::HeterogeneousList));
-                    }
                 }
                 _ => break,
             }
         }
 
+        // sub-lists only if every item is one, so `* [1]` and `* see note` are strings
+        let mixed = items.iter().zip(&inferred).any(|(item, written)| written.is_none() && !item.is_na());
+        if mixed {
+            for (item, written) in items.iter_mut().zip(inferred) {
+                if let Some(written) = written {
+                    *item = Value::new(ValueBase::Str(written), item.get_annotation(), ValueType::Str, item.span());
+                }
+            }
+        }
+
+        // init list with the type of the first item, then check the others
+        let mut items = items.into_iter();
+        let first_item = items.next().unwrap();
+        let item_typ = first_item.typ().clone();
+        let mut list = List::new(item_typ, first_item);
+        for new_item in items {
+            match list.item_type().merge(new_item.typ()) {
+                Some(t) => {
+                    list.set_item_type(t);
+                    list.push_item(new_item);
+                }
+                None => return Err(ReamError::TypeError(TypeErrorType::HeterogeneousList)),
+            }
+        }
+
+        let typ = ValueType::List(Box::new(list.item_type().clone()));
+        let end = list.last_item_span().end();
         let value_base = ValueBase::new_item(list);
-        let typ = ValueType::List(Box::new(item_typ.clone()));
-        Ok((value_base, typ))
+        Ok((value_base, typ, end))
+    }
+
+    // untyped items in brackets are sub-lists if they hold scalars, e.g. `[1, 2]` but not `[sic]`;
+    // such items come with their text
+    fn item_type(&mut self, typ: &ValueType) -> Result<(ValueType, Option<String>), ReamError> {
+        let value = match self.scanner.peek_token()? {
+            Some(Token(TokenType::Value(v), _, _)) if *typ == ValueType::Unknown => v.clone(),
+            _ => return Ok((typ.clone(), None)),
+        };
+        match self.is_scalar_list(&value) {
+            true => Ok((ValueType::List(Box::new(ValueType::Unknown)), Some(value))),
+            false => Ok((typ.clone(), None)),
+        }
+    }
+
+    // items in brackets, each missing or a number or boolean, all of one type
+    fn is_scalar_list(&self, value: &str) -> bool {
+        if !is_bracketed(value) {
+            return false;
+        }
+        let mut typ = ValueType::Unknown;
+        for item in split_items(&value[1..value.len() - 1]) {
+            if self.is_missing(item) {
+                continue;
+            }
+            typ = match ValueBase::new(item.to_string(), ValueType::Unknown) {
+                Ok((_, t)) if t != ValueType::Str => match typ.merge(&t) {
+                    Some(t) => t,
+                    None => return false,
+                },
+                _ => return false,
+            };
+        }
+        typ != ValueType::Unknown
     }
 
     pub fn parse_annotation(&mut self) -> Result<Option<String>, ReamError> {
@@ -374,7 +820,7 @@ impl<'source> Parser<'source> {
                 t
             }
             // value type not specified
-            Some(Token(TokenType::Colon, _, _)) => ValueType::Unknown,
+            Some(Token(TokenType::Colon, _, _)) | Some(Token(TokenType::Equals, _, _)) => ValueType::Unknown,
             // maybe unreachable?
             _ => return Err(ReamError::ParseError(ParseErrorType::MissingColon)),
         };
@@ -382,6 +828,22 @@ impl<'source> Parser<'source> {
         Ok(typ)
     }
 
+    // the unit of a numeric type, e.g. `km2` in `(num km2)`
+    pub fn parse_unit(&mut self) -> Result<Option<String>, ReamError> {
+        match self.scanner.peek_token()? {
+            Some(Token(TokenType::Unit(_), _, _)) => match self.scanner.take_token()? {
+                Some(Token(TokenType::Unit(unit), _, _)) => {
+                    if Unit::parse(&unit).is_none() {
+                        return Err(ReamError::TypeError(TypeErrorType::UnknownUnit(unit)));
+                    }
+                    Ok(Some(unit))
+                }
+                _ => Err(ReamError::Placeholder),
+            },
+            _ => Ok(None),
+        }
+    }
+
     pub fn parse_colon(&mut self) -> Result<(), ReamError> {
         match self.scanner.take_token()? {
             Some(Token(TokenType::Colon, _, _)) => Ok(()),
@@ -390,10 +852,49 @@ impl<'source> Parser<'source> {
     }
 }
 
+fn is_bracketed(value: &str) -> bool {
+    value.starts_with('[') && value.ends_with(']')
+}
+
+// items separated by commas outside brackets
+fn split_items(value: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in value.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(value[start..].trim());
+    items
+}
+
+// Levenshtein distance, counted in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + if ca == *cb { 0 } else { 1 };
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
 #[cfg(test)]
 mod tests {
 
-    // use super::*;
+    use super::*;
 
     // #[test]
     // fn header_line() {
@@ -418,4 +919,715 @@ mod tests {
     //     entry_ans.push_variable(var);
     //     assert_eq!(entry_test, entry_ans);
     // }
+
+    #[test]
+    fn schema_consistent_types() {
+        let text = "# Country\n- name: Belgium\n\n## Language\n- size: 0.59\n\n## Language\n- size: 0.4";
+        let mut parser = Parser::new(&text);
+        assert!(parser.parse_entry().is_ok());
+    }
+
+    #[test]
+    fn schema_type_drift() {
+        let text = "# Region\n- name: Europe\n\n## Country\n- population: 11433256\n\n## Country\n- population: unknown";
+        let mut parser = Parser::new(&text);
+        match parser.parse_entry() {
+            Err(ReamError::SchemaError(SchemaErrorType::IncompatibleTypes(c))) => {
+                assert_eq!(c.key, "population");
+                assert_eq!(c.expected, ValueType::Int);
+                assert_eq!(c.expected_at.line(), 4);
+                assert_eq!(c.found, ValueType::Str);
+                assert_eq!(c.found_at.line(), 7);
+            }
+            r => panic!("expected type conflict, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn schema_list_item_drift() {
+        let text = "# Root\n\n## Item\n- xs:\n  * 1\n  * 2\n\n## Item\n- xs:\n  * a\n  * b";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::SchemaError(SchemaErrorType::IncompatibleTypes(_)))
+        ));
+    }
+
+    #[test]
+    fn na_any_type() {
+        let text = "# Root\n\n## Country\n- population: 11433256\n\n## Country\n- population: NA\n\n## Country\n- population (num): NA";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "11433256\nNA\nNA\n");
+    }
+
+    #[test]
+    fn na_custom_literal() {
+        let text = "# Root\n\n## Country\n- population: 11433256\n\n## Country\n- population: .";
+        let options = ParserOptions { na: ".".to_string(), ..ParserOptions::default() };
+        let mut parser = Parser::with_options(&text, options);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        let csv_options = CsvOptions { missing: "".to_string(), ..CsvOptions::default() };
+        assert_eq!(entry.to_csv_str_with(&csv_options).unwrap(), "11433256\n\n");
+    }
+
+    #[test]
+    fn optional_keys() {
+        let text = "# Root\n\n## Country\n- name: Belgium\n- population: 11433256\n\n## Country\n- name: Narnia\n- motto?: Unity\n- population: NA\n\n## Country\n- name: Oz\n- population: 1";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(
+            entry.to_csv_str().unwrap(),
+            "Belgium,NA,11433256\nNarnia,Unity,NA\nOz,NA,1\n"
+        );
+    }
+
+    #[test]
+    fn missing_required_key() {
+        let text = "# Root\n\n## Country\n- name: Belgium\n- population: 11433256\n\n## Country\n- name: Narnia";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::SchemaError(SchemaErrorType::IncorrectKeys))
+        ));
+    }
+
+    #[test]
+    fn missing_codes() {
+        let text = "@ MISSING(refused, unknown)\n# Root\n\n## Person\n- age: 31\n\n## Person\n- age: NA(unknown)\n\n## Person\n- age: NA(refused)\n\n## Person\n- age: NA";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+
+        let mut options = CsvOptions::default();
+        assert_eq!(entry.to_csv_str_with(&options).unwrap(), "31\nNA\nNA\nNA\n");
+        options.missing_style = MissingStyle::Label;
+        assert_eq!(entry.to_csv_str_with(&options).unwrap(), "31\nunknown\nrefused\nNA\n");
+        options.missing_style = MissingStyle::Stata;
+        assert_eq!(entry.to_csv_str_with(&options).unwrap(), "31\n.b\n.a\n.\n");
+        options.missing_style = MissingStyle::Spss;
+        assert_eq!(entry.to_csv_str_with(&options).unwrap(), "31\n-2\n-1\nNA\n");
+
+        assert_eq!(
+            entry.to_summary_str().unwrap(),
+            "class,key,missing,count\nPerson,age,NA,1\nPerson,age,NA(refused),1\nPerson,age,NA(unknown),1\n"
+        );
+    }
+
+    #[test]
+    fn undeclared_missing_code() {
+        // an undeclared code is an ordinary value, checked against the type
+        let text = "@ MISSING(refused)\n# Person\n- age (int): NA(unknown)";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::TypeError(TypeErrorType::InvalidInteger))
+        ));
+
+        let text = "# Region\n- name: NA(North America)";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let name = entry.value(&"name".to_string()).unwrap();
+        assert!(!name.is_na());
+        assert_eq!(name.typ(), &ValueType::Str);
+        assert_eq!(name.get_raw(), "NA(North America)");
+    }
+
+    #[test]
+    fn missing_codes_on_root_only() {
+        let text = "# Root\n\n@@ MISSING(refused)\n## Person\n- age: 31";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::DecoratorError(DecoratorErrorType::MisplacedDecorator))
+        ));
+    }
+
+    #[test]
+    fn templates() {
+        let text = "# World\n\n@@ TEMPLATE(country)\n## Country\n- continent: Europe\n- eu: TRUE\n\n### Language\n- name: English\n\n@@ USE(country)\n## Country\n- name: Belgium\n\n### Language\n- name: Dutch\n\n@@ USE(country)\n## Country\n- eu: FALSE\n- name: Norway";
+        let written = "# World\n\n## Country\n- continent: Europe\n- eu: TRUE\n- name: Belgium\n\n### Language\n- name: English\n\n### Language\n- name: Dutch\n\n## Country\n- continent: Europe\n- eu: FALSE\n- name: Norway\n\n### Language\n- name: English";
+        let generated = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let written = Parser::new(&written).parse_entry().unwrap().unwrap();
+        assert_eq!(generated.to_string(), written.to_string());
+        assert_eq!(generated.to_csv_str().unwrap(), written.to_csv_str().unwrap());
+    }
+
+    #[test]
+    fn loops() {
+        let text = "# Country\n- name: Belgium\n\n@@ FOR(year, 1990..1992)\n## Observation\n- gdp: NA\n\n### Source\n- name: IMF";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(
+            entry.to_csv_str().unwrap(),
+            "Belgium,1990,NA,IMF\nBelgium,1991,NA,IMF\nBelgium,1992,NA,IMF\n"
+        );
+        assert!(!entry.to_string().contains("FOR"));
+
+        let text = "# Country\n\n@@ FOR(code, BE, FR)\n## Region\n- label = code + \"-1\"";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "BE,BE-1\nFR,FR-1\n");
+    }
+
+    #[test]
+    fn loops_of_template_instances() {
+        for decorators in ["@@ USE(obs)\n@@ FOR(year, 1990..1992)", "@@ FOR(year, 1990..1992)\n@@ USE(obs)"] {
+            let text = format!("# Country\n\n@@ TEMPLATE(obs)\n## Observation\n- source: IMF\n\n{}\n## Observation\n- gdp: 1", decorators);
+            let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+            assert_eq!(entry.to_csv_str().unwrap(), "1990,IMF,1\n1991,IMF,1\n1992,IMF,1\n");
+        }
+    }
+
+    #[test]
+    fn template_errors() {
+        let text = "# World\n\n@@ USE(country)\n## Country\n- name: Belgium";
+        assert!(matches!(
+            Parser::new(&text).parse_entry(),
+            Err(ReamError::DecoratorError(DecoratorErrorType::TemplateNotFound(_)))
+        ));
+
+        let text = "@ FOR(year, 1990..1992)\n# Country";
+        assert!(matches!(
+            Parser::new(&text).parse_entry(),
+            Err(ReamError::DecoratorError(DecoratorErrorType::MisplacedDecorator))
+        ));
+
+        let text = "# Country\n\n@@ FOR(year, 1992..1990)\n## Observation\n- gdp: 1";
+        assert!(matches!(
+            Parser::new(&text).parse_entry(),
+            Err(ReamError::DecoratorError(DecoratorErrorType::InvalidDecorator))
+        ));
+
+        // ranges are limited
+        let text = "# Country\n\n@@ FOR(i, 1..1000000000)\n## Observation\n- gdp: 1";
+        assert!(matches!(
+            Parser::new(&text).parse_entry(),
+            Err(ReamError::DecoratorError(DecoratorErrorType::InvalidDecorator))
+        ));
+        let text = "# Country\n\n@@ FOR(i, 1..10000)\n## Observation\n- gdp: 1";
+        assert_eq!(Parser::new(&text).parse_entry().unwrap().unwrap().subentries().len(), 10000);
+    }
+
+    #[test]
+    fn loop_missing_values() {
+        let text = "# Country\n\n@@ FOR(year, 1990, NA)\n## Observation\n- gdp: 1";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let years: Vec<&Value> = entry.subentries().iter().map(|e| e.value(&"year".to_string()).unwrap()).collect();
+        assert_eq!(years[0].typ(), &ValueType::Int);
+        assert!(years[1].is_na());
+    }
+
+    #[test]
+    fn class_defaults() {
+        let text = "# World\n\n@@ DEFAULT(Language$source, WALS)\n@@ DEFAULT(Language$round, 2)\n## Country\n- name: Belgium\n\n### Language\n- name: Dutch\n\n### Language\n- name: French\n- source: Ethnologue\n\n## Country\n- name: Chile\n\n### Language\n- name: Spanish\n- source: INE\n- round: 1";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(
+            entry.to_csv_str().unwrap(),
+            "Belgium,Dutch,WALS,2\nBelgium,French,Ethnologue,2\nChile,Spanish,INE,1\n"
+        );
+        let dutch = entry.entry_at(&[0, 0]).unwrap();
+        assert_eq!(dutch.value(&"source".to_string()).unwrap().provenance(), &Provenance::Inherited);
+        assert_eq!(dutch.value(&"name".to_string()).unwrap().provenance(), &Provenance::Explicit);
+        let french = entry.entry_at(&[0, 1]).unwrap();
+        assert_eq!(french.value(&"source".to_string()).unwrap().provenance(), &Provenance::Explicit);
+
+        // inherited values are not written out
+        let written = entry.to_string();
+        assert!(written.contains("@@ DEFAULT(Language$source, WALS)\n@@ DEFAULT(Language$round, 2)\n## Country"));
+        assert!(!written.contains("WALS\n"));
+        let reparsed = Parser::new(&written).parse_entry().unwrap().unwrap();
+        assert_eq!(reparsed.to_csv_str().unwrap(), entry.to_csv_str().unwrap());
+    }
+
+    #[test]
+    fn class_defaults_follow_schema() {
+        // `source` is placed as in the schema, after `name`
+        let text = "# Country\n\n@@ DEFAULT(Language$source, WALS)\n## Region\n\n### Language\n- name: Dutch\n- source: Ethnologue\n- speakers: 6\n\n### Language\n- name: French\n- speakers: 4";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let french = entry.entry_at(&[0, 1]).unwrap();
+        assert_eq!(french.keys(), vec!["name", "source", "speakers"]);
+    }
+
+    #[test]
+    fn recursive_classes() {
+        let text = "# World\n\n@@ RECURSIVE\n## Region\n- name: Europe\n\n### Region\n- name: Benelux\n\n#### Country\n- name: Belgium\n\n#### Region\n- name: Flanders\n\n### Region\n- name: Nordics\n\n## Region\n- name: Asia";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(
+            entry.to_csv_str().unwrap(),
+            "1,1,Europe\n1/1,2,Benelux,Belgium\n1/1/1,3,Flanders\n1/2,2,Nordics\n2,1,Asia\n"
+        );
+
+        // undeclared self-nesting is rejected
+        let text = "# World\n\n## Region\n- name: Europe\n\n### Region\n- name: Benelux";
+        assert!(matches!(
+            Parser::new(&text).parse_entry(),
+            Err(ReamError::SchemaError(SchemaErrorType::IncorrectParentClass))
+        ));
+    }
+
+    #[test]
+    fn permitted_parents() {
+        let text = "# World\n\n## Country\n- name: Belgium\n\n@@@ PARENTS(Country, Union)\n### Office\n- city: Brussels\n\n## Union\n- name: EU\n\n### Office\n- city: Strasbourg";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "Belgium,Brussels\nEU,Strasbourg\n");
+
+        let text = "# World\n\n## Country\n- name: Belgium\n\n@@@ PARENTS(Union)\n### Office\n- city: Brussels";
+        assert!(matches!(
+            Parser::new(&text).parse_entry(),
+            Err(ReamError::SchemaError(SchemaErrorType::IncorrectParentClass))
+        ));
+    }
+
+    #[test]
+    fn entry_annotations() {
+        let text = "# Country\n> Sovereign states only.\n> Data as of 2020.\n- name: Belgium\n  > official name\n\n## Language\n- name: Dutch";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.annotation().unwrap(), "Sovereign states only.\nData as of 2020.");
+        assert_eq!(entry.entry_at(&[0]).unwrap().annotation(), None);
+
+        let written = entry.to_string();
+        assert!(written.starts_with("# Country\n> Sovereign states only.\n> Data as of 2020.\n- name"));
+        let reparsed = Parser::new(&written).parse_entry().unwrap().unwrap();
+        assert_eq!(reparsed.annotation(), entry.annotation());
+
+        let options = CsvOptions { annotations: true, ..CsvOptions::default() };
+        assert_eq!(
+            entry.to_csv_str_with(&options).unwrap(),
+            "Belgium,Sovereign states only. Data as of 2020.,Dutch,\n"
+        );
+
+        // fields with commas or quotes are quoted
+        let text = "# Country\n> Sovereign, \"recognised\"\n- name: Belgium";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(
+            entry.to_csv_str_with(&options).unwrap(),
+            "Belgium,\"Sovereign, \"\"recognised\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn date_types() {
+        let text = "# Election\n- held (date): 1999-06-13\n- counted (datetime): 1999-06-13T22:30:00+02:00\n- term (year): 1999\n- called (yearmonth): 1999-04\n- note: 1999-06-13";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let typ = |key: &str| entry.value(&key.to_string()).unwrap().typ().clone();
+        assert_eq!(typ("held"), ValueType::Date);
+        assert_eq!(typ("counted"), ValueType::DateTime);
+        assert_eq!(typ("term"), ValueType::Year);
+        assert_eq!(typ("called"), ValueType::YearMonth);
+        // untyped dates are strings
+        assert_eq!(typ("note"), ValueType::Str);
+        assert_eq!(
+            entry.to_csv_str().unwrap(),
+            "1999-06-13,1999-06-13T22:30:00+02:00,1999,1999-04,1999-06-13\n"
+        );
+        let reparsed = Parser::new(&entry.to_string()).parse_entry().unwrap().unwrap();
+        assert_eq!(reparsed.value(&"counted".to_string()), entry.value(&"counted".to_string()));
+
+        for invalid in ["(date): 1999-02-29", "(date): 1999-6-13", "(yearmonth): 1999-13", "(year): 99", "(datetime): 1999-06-13T24:00"] {
+            let text = format!("# Election\n- held {}", invalid);
+            assert!(matches!(
+                Parser::new(&text).parse_entry(),
+                Err(ReamError::TypeError(TypeErrorType::InvalidDate))
+            ));
+        }
+        assert!(Parser::new("# Election\n- held (date): 2000-02-29").parse_entry().is_ok());
+    }
+
+    #[test]
+    fn integers_and_floats() {
+        let text = "# Country\n- population: 11\n- share: 0.6\n- name: Inf\n- code: 1e5\n- motto: NaN\n- area (num): 3.05e4\n- sizes (list):\n  * 1\n  * 2.5\n- seats (int): 150\n- double (int) = seats * 2\n\n## Region\n- share: 1\n\n## Region\n- share: 0.5";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        let typ = |key: &str| entry.value(&key.to_string()).unwrap().typ().clone();
+        assert_eq!(typ("population"), ValueType::Int);
+        assert_eq!(typ("share"), ValueType::Num);
+        // non-finite values and exponents are not inferred as numbers
+        assert_eq!(typ("name"), ValueType::Str);
+        assert_eq!(typ("code"), ValueType::Str);
+        assert_eq!(typ("motto"), ValueType::Str);
+        assert_eq!(typ("area"), ValueType::Num);
+        assert_eq!(typ("sizes"), ValueType::List(Box::new(ValueType::Num)));
+        assert_eq!(typ("double"), ValueType::Int);
+        // `int` widens to `num` between entries
+        let schema = parser.get_schema("Region".to_string()).unwrap();
+        assert_eq!(schema.field("share").unwrap().typ(), &ValueType::Num);
+        // but not if declared `(int)` in any entry
+        for text in [
+            "# Country\n\n## Region\n- share (int): 1\n\n## Region\n- share: 0.5",
+            "# Country\n\n## Region\n- share: 0.5\n\n## Region\n- share (int): 1",
+            "# Country\n\n## Region\n- share (int): 1\n\n## Region\n- share (num): 0.5",
+        ] {
+            assert!(matches!(
+                Parser::new(text).parse_entry(),
+                Err(ReamError::SchemaError(SchemaErrorType::IncompatibleTypes(_)))
+            ));
+        }
+        let text = "# Country\n\n## Region\n- share: 1\n\n## Region\n- share (num): 0.5";
+        assert!(Parser::new(text).parse_entry().is_ok());
+
+        // a leading `.`
+        let entry = Parser::new("# Country\n- share (num): .5\n- growth: -.5").parse_entry().unwrap().unwrap();
+        assert_eq!(entry.value(&"share".to_string()).unwrap().get_raw(), ".5");
+        assert_eq!(entry.value(&"growth".to_string()).unwrap().typ(), &ValueType::Num);
+
+        for (invalid, error) in [
+            ("(num): inf", TypeErrorType::InvalidNumber),
+            ("(num): 1e999", TypeErrorType::InvalidNumber),
+            ("(int): 1.5", TypeErrorType::InvalidInteger),
+        ] {
+            let text = format!("# Country\n- size {}", invalid);
+            match Parser::new(&text).parse_entry() {
+                Err(ReamError::TypeError(e)) => assert_eq!(
+                    std::mem::discriminant(&e),
+                    std::mem::discriminant(&error)
+                ),
+                r => panic!("expected type error, got {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_numbers() {
+        let text = "# Country\n- population: +0011\n- share: 0.60\n- area (num): 3.050E+04\n- balance: -0.0";
+        let options = ParserOptions { canonical_numbers: true, ..ParserOptions::default() };
+        let entry = Parser::with_options(&text, options).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "11,0.6,3.05e4,0\n");
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "+0011,0.60,3.050E+04,-0.0\n");
+    }
+
+    #[test]
+    fn numbers_in_ast() {
+        let text = "# Country\n- share: 0.590\n- debt: 12345678901234567890123456789012345678";
+        let mut entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let ast = entry.to_ast_str().unwrap();
+        assert!(ast.contains(r#"{"Num":"0.590"}"#));
+        assert!(ast.contains(r#"{"Num":"12345678901234567890123456789012345678"}"#));
+        assert_eq!(serde_json::from_str::<Entry>(&ast).unwrap(), entry);
+    }
+
+    #[test]
+    fn categorical_levels() {
+        let text = "@ LEVELS(regime, democracy, hybrid, autocracy)\n# World\n\n## Country\n- name: Belgium\n- regime (cat regime): democracy\n- past (list cat regime):\n  * autocracy\n  * democracy\n\n## Country\n- name: Chile\n- regime (cat regime): hybrid\n- past (list cat regime):\n  * autocracy";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let regime = entry.entry_at(&[0]).unwrap().value(&"regime".to_string()).unwrap();
+        assert_eq!(regime.typ(), &ValueType::Cat("regime".to_string()));
+        assert_eq!(
+            entry.to_csv_str().unwrap(),
+            "Belgium,democracy,autocracy;democracy\nChile,hybrid,autocracy\n"
+        );
+        let options = CsvOptions { categories: CategoryStyle::Code, ..CsvOptions::default() };
+        assert_eq!(entry.to_csv_str_with(&options).unwrap(), "Belgium,1,3;1\nChile,2,3\n");
+        assert_eq!(
+            entry.to_levels_str().unwrap(),
+            "levels,code,level\nregime,1,democracy\nregime,2,hybrid\nregime,3,autocracy\n"
+        );
+        let written = entry.to_string();
+        assert!(written.starts_with("@ LEVELS(regime, democracy, hybrid, autocracy)\n# World"));
+        assert!(written.contains("- regime (cat regime): democracy"));
+
+        // values outside the levels suggest the nearest one
+        let text = "@ LEVELS(regime, democracy, hybrid, autocracy)\n# Country\n- regime (cat regime): democrcy";
+        match Parser::new(&text).parse_entry() {
+            Err(ReamError::TypeError(TypeErrorType::UnknownLevel(m))) => {
+                assert_eq!(m.value, "democrcy");
+                assert_eq!(m.nearest, Some("democracy".to_string()));
+                assert_eq!(m.span.start().line(), 3);
+            }
+            r => panic!("expected unknown level, got {:?}", r),
+        }
+        assert!(matches!(
+            Parser::new("# Country\n- regime (cat regime): democracy").parse_entry(),
+            Err(ReamError::TypeError(TypeErrorType::UndeclaredLevels(_)))
+        ));
+    }
+
+    #[test]
+    fn units_convert_to_declared() {
+        let text = "# Root\n\n## Country\n- area (num km2): 3052800 ha\n- population (int persons): 11.4 million\n\n## Country\n- area (num km2): 30688\n- population (int persons): 11433256";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "30528,11400000\n30688,11433256\n");
+        assert_eq!(
+            entry.to_units_str().unwrap(),
+            "class,key,unit\nCountry,area,km2\nCountry,population,persons\n"
+        );
+
+        // exactly, also to smaller units
+        let text = "# Country\n- border (num m): 0.7 km\n- coast (int m): 66.5 km\n- park (int m2): 1.5 ha\n- forest (num mi2): 2.589988110336 km2";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "700,66500,15000,1\n");
+    }
+
+    #[test]
+    fn units_inline() {
+        let text = "# Root\n\n## Country\n- gdp: 599.9 billion USD";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        let country = &entry.subentries()[0];
+        assert_eq!(country.value(&"gdp".to_string()).unwrap().unit().unwrap(), "billion USD");
+        assert!(entry.to_string().contains("- gdp (num billion USD): 599.9"));
+    }
+
+    #[test]
+    fn units_incompatible() {
+        let mut parser = Parser::new("# Root\n- area (num km2): 12 kg");
+        match parser.parse_entry() {
+            Err(ReamError::TypeError(TypeErrorType::IncompatibleUnit(m))) => {
+                assert_eq!(m.expected, "km2");
+                assert_eq!(m.found, "kg");
+            }
+            r => panic!("expected incompatible unit, got {:?}", r),
+        }
+        let mut parser = Parser::new("# Root\n- area (num parsecs): 12");
+        assert!(matches!(parser.parse_entry(), Err(ReamError::TypeError(TypeErrorType::UnknownUnit(_)))));
+    }
+
+    #[test]
+    fn schema_mixed_units() {
+        let text = "# Root\n\n## Country\n- population: 11.4 million\n\n## Country\n- population: NA\n\n## Country\n- population: 11433256";
+        let mut parser = Parser::new(&text);
+        match parser.parse_entry() {
+            Err(ReamError::SchemaError(SchemaErrorType::MixedUnits(c))) => {
+                assert_eq!(c.key, "population");
+                assert_eq!(c.expected.as_deref(), Some("million"));
+                assert_eq!(c.found, None);
+                assert_eq!(c.found_at.line(), 9);
+            }
+            r => panic!("expected mixed units, got {:?}", r),
+        }
+    }
+
+
+    #[test]
+    fn intervals() {
+        let text = "# Root\n\n## Country\n- population: 2..3 million\n\n## Country\n- population: 2.5 ± 0.5 million\n\n## Country\n- population: 4 million\n\n## Country\n- population: NA";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "2,3,NA\n2,3,2.5\n4,4,4\nNA,NA,NA\n");
+        let options = CsvOptions { intervals: IntervalStyle::Raw, ..CsvOptions::default() };
+        assert_eq!(entry.to_csv_str_with(&options).unwrap(), "2..3\n2.5 ± 0.5\n4\nNA\n");
+        assert!(entry.to_string().contains("- population (num million): 2.5 ± 0.5\n"));
+    }
+
+    #[test]
+    fn intervals_typed() {
+        let text = "# Root\n- area (num km2): 300..400 ha\n- population (int): 2 +- 1";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.value(&"area".to_string()).unwrap().get_raw(), "3..4");
+        assert_eq!(entry.value(&"population".to_string()).unwrap().get_raw(), "2 ± 1");
+
+        for text in ["# Root\n- population (int): 2.5 ± 1", "# Root\n- population (num): 3..2"] {
+            assert!(Parser::new(&text).parse_entry().is_err());
+        }
+    }
+
+    #[test]
+    fn nested_lists() {
+        let indented = "# Root\n- groups (list list int):\n  *\n    * 1\n    * 2\n  *\n    * 3\n- names: x";
+        let bracketed = "# Root\n- groups:\n  * [1, 2]\n  * [3]\n- names: x";
+        let inline = "# Root\n- groups (list list int): [[1, 2], [3]]\n- names: x";
+        for text in [indented, bracketed, inline] {
+            let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+            let groups = entry.value(&"groups".to_string()).unwrap();
+            assert_eq!(groups.typ(), &ValueType::List(Box::new(ValueType::List(Box::new(ValueType::Int)))));
+            assert_eq!(entry.to_csv_str().unwrap(), "[1;2];[3],x\n");
+
+            // written out as indented `*` items
+            let raw = entry.to_string();
+            assert!(raw.contains("  *\n    * 1\n    * 2\n  *\n    * 3\n"));
+            let reparsed = Parser::new(&raw).parse_entry().unwrap().unwrap();
+            assert_eq!(reparsed.to_csv_str().unwrap(), "[1;2];[3],x\n");
+        }
+    }
+
+    #[test]
+    fn bracketed_strings() {
+        let text = "# Root\n- notes:\n  * [citation needed]\n  * [sic]\n  * [1, a]\n  * plain";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let notes = entry.value(&"notes".to_string()).unwrap();
+        assert_eq!(notes.typ(), &ValueType::List(Box::new(ValueType::Str)));
+        assert_eq!(entry.to_csv_str().unwrap(), "\"[citation needed];[sic];[1, a];plain\"\n");
+
+        // sub-lists only if every item is one
+        for text in ["# Root\n- notes:\n  * [1]\n  * see note", "# Root\n- notes:\n  * [1, 2]\n  * [[3]]"] {
+            let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+            let notes = entry.value(&"notes".to_string()).unwrap();
+            assert_eq!(notes.typ(), &ValueType::List(Box::new(ValueType::Str)), "{}", text);
+        }
+        let text = "# Root\n- groups:\n  * [1]\n  * NA";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let groups = entry.value(&"groups".to_string()).unwrap();
+        assert_eq!(groups.typ(), &ValueType::List(Box::new(ValueType::List(Box::new(ValueType::Int)))));
+
+        // but declared sub-lists of strings
+        let text = "# Root\n- notes (list list str):\n  * [citation needed]\n  * [sic, a]";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let notes = entry.value(&"notes".to_string()).unwrap();
+        assert_eq!(notes.typ(), &ValueType::List(Box::new(ValueType::List(Box::new(ValueType::Str)))));
+    }
+
+    #[test]
+    fn nested_lists_type_checked() {
+        for text in [
+            "# Root\n- groups (list list int):\n  *\n    * 1\n    * a",
+            "# Root\n- groups (list int):\n  *\n    * 1",
+        ] {
+            assert!(matches!(Parser::new(&text).parse_entry(), Err(ReamError::TypeError(_))), "{}", text);
+        }
+
+        // a single item where a sub-list is declared
+        let text = "# Root\n- groups (list list int):\n  * 1";
+        match Parser::new(&text).parse_entry() {
+            Err(ReamError::TypeError(TypeErrorType::NotAList(m))) => {
+                assert_eq!(m.expected, ValueType::List(Box::new(ValueType::Int)));
+                assert_eq!(m.value, "1");
+                assert_eq!(m.span.start(), Marker::new(3, 5));
+            }
+            r => panic!("expected a single value, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn literal_profile_pragma() {
+        let text = "@ LITERALS(de)\n# Root\n\n## Country\n- share: 0,59\n- population: 11.433.256\n- eu: ja\n\n## Country\n- share: 0,41\n- population: 1.000\n- eu: nein";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "0.59,11433256,TRUE\n0.41,1000,FALSE\n");
+
+        // written as given
+        let raw = entry.to_string();
+        assert!(raw.contains("- share (num): 0,59\n- population (int): 11.433.256\n- eu (bool): ja\n"));
+        let reparsed = Parser::new(&raw).parse_entry().unwrap().unwrap();
+        assert_eq!(reparsed.to_csv_str().unwrap(), "0.59,11433256,TRUE\n0.41,1000,FALSE\n");
+    }
+
+    #[test]
+    fn literal_profile_option() {
+        let text = "# Root\n- gdp: 1,234.5\n- ok: yes\n- code: 1,2";
+        let options = ParserOptions { literals: LiteralProfile::named("en").unwrap(), ..ParserOptions::default() };
+        let entry = Parser::with_options(&text, options).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "1234.5,TRUE,\"1,2\"\n");
+        assert_eq!(entry.value(&"code".to_string()).unwrap().typ(), &ValueType::Str);
+
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.value(&"ok".to_string()).unwrap().typ(), &ValueType::Str);
+
+        // numbers within text are kept as written, whole quantities are not
+        for (name, note, size) in [
+            ("de", "Gegründet 1.830 mit 2,5 Mio", "1.200,5 million"),
+            ("en", "between 100,200 and 1,000 people", "1,200.5 million"),
+        ] {
+            let text = format!("# Root\n- note: {}\n- size: {}", note, size);
+            let options = ParserOptions { literals: LiteralProfile::named(name).unwrap(), ..ParserOptions::default() };
+            let entry = Parser::with_options(&text, options).parse_entry().unwrap().unwrap();
+            let value = |key: &str| entry.value(&key.to_string()).unwrap();
+            assert_eq!(value("note").get_raw(), note);
+            assert_eq!(value("note").typ(), &ValueType::Str);
+            assert_eq!(value("size").get_raw(), "1200.5");
+        }
+    }
+
+    #[test]
+    fn constraints() {
+        let text = "@ CHECK(Country$population, population > 0)\n@ CHECK(Country$code, code ~ ^[A-Z]{3}$)\n@ CHECK(Country$languages, len(languages) <= 2)\n# World\n\n## Country\n- population: 11433256\n- code: BEL\n- languages (list str):\n  * Dutch\n  * French\n\n## Country\n- population: NA\n- code: CHL\n- languages (list str):\n  * Spanish";
+        let mut parser = Parser::new(&text);
+        assert!(parser.parse_entry().is_ok());
+
+        let text_code = text.replace("CHL", "Chile");
+        let mut parser = Parser::new(&text_code);
+        match parser.parse_entry() {
+            Err(ReamError::ConstraintError(v)) => {
+                assert_eq!(v.key, "code");
+                assert_eq!(v.value, "Chile");
+                assert_eq!(v.constraint, "code ~ ^[A-Z]{3}$");
+                assert_eq!(v.span.start().line(), 15);
+            }
+            r => panic!("expected constraint violation, got {:?}", r),
+        }
+
+        let text_length = text.replace("* Spanish", "* Spanish\n  * Mapudungun\n  * Quechua");
+        let mut parser = Parser::new(&text_length);
+        assert!(matches!(parser.parse_entry(), Err(ReamError::ConstraintError(v)) if v.key == "languages"));
+    }
+
+    #[test]
+    fn constraints_range() {
+        let text = "# World\n\n@@ CHECK(Region$share, 0 <= share <= 1)\n## Country\n\n### Region\n- share: 0.25\n\n### Region\n- share: 0.5..1.25";
+        let mut parser = Parser::new(&text);
+        let error = parser.parse_entry().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "ConstraintError: `0.5..1.25` in key `share` of `Region` at line 10, col 10-18 violates `0 <= share <= 1`"
+        );
+
+        // constraints apply within the entry declaring them
+        let mut parser = Parser::new("# World\n\n## Country\n- share: 2\n\n@@ CHECK(Country$share, share <= 1)\n## Country\n- share: 1");
+        assert!(parser.parse_entry().is_ok());
+        assert!(Decorator::from("CHECK(Country$share, size <= 1)".to_string()).is_err());
+
+        // literals are numbers or dates
+        for (key, invalid) in [("code", "code == BEL"), ("code", "len(code) <= 2000-01-01"), ("share", "share < one")] {
+            assert!(matches!(
+                Decorator::from(format!("CHECK(Country${}, {})", key, invalid)),
+                Err(ReamError::DecoratorError(DecoratorErrorType::InvalidDecorator))
+            ));
+        }
+        assert!(Decorator::from("CHECK(Country$founded, founded >= 1830-10)".to_string()).is_ok());
+    }
+
+    struct Iso3;
+
+    impl ReamType for Iso3 {
+        fn name(&self) -> &str {
+            "iso3"
+        }
+
+        fn validate(&self, value: &str) -> bool {
+            value.len() == 3 && value.bytes().all(|b| b.is_ascii_alphabetic())
+        }
+
+        fn canonicalize(&self, value: &str) -> String {
+            value.to_ascii_uppercase()
+        }
+    }
+
+    struct Latitude;
+
+    impl ReamType for Latitude {
+        fn name(&self) -> &str {
+            "lat"
+        }
+
+        fn validate(&self, value: &str) -> bool {
+            value.parse::<f64>().is_ok_and(|lat| (-90.0..=90.0).contains(&lat))
+        }
+
+        fn export_type(&self) -> ValueType {
+            ValueType::Num
+        }
+    }
+
+    fn custom_options() -> ParserOptions {
+        let mut types = TypeRegistry::new();
+        types.register(Iso3);
+        types.register(Latitude);
+        ParserOptions { types, ..ParserOptions::default() }
+    }
+
+    #[test]
+    fn custom_types() {
+        let text = "# Country\n- code (iso3): bel\n- neighbours (list iso3): [FRA, nld]\n- capital (lat): 50.85";
+        let entry = Parser::with_options(text, custom_options()).parse_entry().unwrap().unwrap();
+        let code = entry.value(&"code".to_string()).unwrap();
+        assert_eq!(code.typ(), &ValueType::Custom("iso3".to_string()));
+        assert_eq!(code.get_raw(), "BEL");
+        assert_eq!(entry.value(&"neighbours".to_string()).unwrap().get_raw(), "FRA;NLD");
+        let capital = entry.value(&"capital".to_string()).unwrap();
+        assert_eq!(capital.as_f64(), Some(50.85));
+        assert!(entry.to_string().contains("- capital (lat): 50.85"));
+
+        let error = Parser::with_options("# Country\n- code (iso3): Belgium", custom_options()).parse_entry().unwrap_err();
+        assert_eq!(error.to_string(), "TypeError: `Belgium` at line 2, col 16-22 is not a valid `iso3`");
+
+        // unregistered names are still unknown
+        let mut parser = Parser::new("# Country\n- code (iso3): BEL");
+        assert!(matches!(parser.parse_entry(), Err(ReamError::TypeError(TypeErrorType::UnknownType))));
+    }
 }
@@ -0,0 +1,1145 @@
+use crate::error::*;
+use crate::expression::{EvalError, Operand};
+use crate::format::*;
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+// Indices of subentries from the root entry.
+pub type Path = Vec<usize>;
+
+// A variable in the tree: the path of its entry and its key.
+pub type Node = (Path, String);
+
+// Where a reference points to.
+enum Target {
+    Upstream(Node),        // the nearest ancestor of the class
+    Downstream(Vec<Node>), // every descendant of the class
+    Identified(Node),      // the entry of the class with the identifier
+    Aggregated(Aggregate, Vec<Node>), // every descendant of the class, aggregated
+    Counted(usize),        // number of descendants of the class
+    Sibling(Node),         // a key of the same entry
+    Computed(Vec<Target>), // operands of an expression
+}
+
+#[derive(PartialEq, Eq)]
+enum Mark {
+    Visiting,
+    Done,
+}
+
+// Resolve every reference in the tree.
+//
+// References form a graph: a reference to a key holding another reference
+// depends on it, and is resolved after it.
+// Cycles between references are reported with all references involved.
+pub fn resolve(root: &mut Entry) -> Result<(), ReamError> {
+    resolve_with_dependencies(root).map(|_| ())
+}
+
+// Resolve every reference in the tree, keeping which references read which variables.
+pub fn resolve_with_dependencies(root: &mut Entry) -> Result<Dependencies, ReamError> {
+    let (resolved, dependencies) = Resolver::new(root).resolve_with_dependencies()?;
+    set_values(root, resolved);
+    Ok(dependencies)
+}
+
+// Resolve again the references reading `changed`, directly or through other references,
+// after its value changed in both trees; other references keep their values in `resolved`.
+// The value must not be a reference itself, and identifiers must be unchanged.
+// Returns the references resolved again.
+pub fn resolve_dependents(
+    unresolved: &Entry,
+    resolved: &mut Entry,
+    dependencies: &Dependencies,
+    changed: &Node,
+) -> Result<Vec<Node>, ReamError> {
+    let mut affected = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = vec![changed];
+    while let Some(node) = queue.pop() {
+        for dependent in dependencies.dependents.get(node).into_iter().flatten() {
+            if seen.insert(dependent) {
+                affected.push(dependent.clone());
+                queue.push(dependent);
+            }
+        }
+    }
+
+    let mut resolver = Resolver::new(unresolved);
+    resolver.ids = Cow::Borrowed(&dependencies.ids);
+    resolver.check_foreign_keys_of(resolver.entry(&changed.0))?;
+    let values = {
+        resolver.known = Some(resolved);
+        resolver.pending = Some(seen.into_iter().cloned().collect());
+        for node in &affected {
+            resolver.visit(node)?;
+        }
+        resolver.resolved
+    };
+    set_values(resolved, values);
+    Ok(affected)
+}
+
+fn set_values(root: &mut Entry, values: HashMap<Node, Value>) {
+    for ((path, key), value) in values {
+        match root.entry_at_mut(&path) {
+            Some(entry) => entry.set_value(key, value),
+            None => unreachable!(),
+        }
+    }
+}
+
+// What resolving learns of the tree, to resolve again after a value changes.
+#[derive(Debug, Clone, Default)]
+pub struct Dependencies {
+    ids: HashMap<(String, String), Path>, // (class, id) -> entry
+    dependents: HashMap<Node, Vec<Node>>, // variable -> references reading it
+}
+
+pub struct Resolver<'a> {
+    root: &'a Entry,
+    ids: Cow<'a, HashMap<(String, String), Path>>, // (class, id) -> entry
+    resolved: HashMap<Node, Value>,
+    marks: HashMap<Node, Mark>,
+    stack: Vec<Node>, // references being resolved, for cycle detection
+    dependents: HashMap<Node, Vec<Node>>,
+    known: Option<&'a Entry>,         // resolved tree, for references not resolved again
+    pending: Option<HashSet<Node>>,   // the references to resolve again, if not all
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(root: &'a Entry) -> Self {
+        Self {
+            root,
+            ids: Cow::Owned(HashMap::new()),
+            resolved: HashMap::new(),
+            marks: HashMap::new(),
+            stack: Vec::new(),
+            dependents: HashMap::new(),
+            known: None,
+            pending: None,
+        }
+    }
+
+    pub fn resolve(self) -> Result<HashMap<Node, Value>, ReamError> {
+        self.resolve_with_dependencies().map(|(resolved, _)| resolved)
+    }
+
+    pub fn resolve_with_dependencies(mut self) -> Result<(HashMap<Node, Value>, Dependencies), ReamError> {
+        let mut ids = HashMap::new();
+        self.index_ids(self.root, &mut Vec::new(), &mut ids)?;
+        self.ids = Cow::Owned(ids);
+        self.check_foreign_keys(self.root)?;
+
+        let mut nodes = Vec::new();
+        collect_ref_nodes(self.root, &mut Vec::new(), &mut nodes);
+
+        for node in nodes {
+            self.visit(&node)?;
+        }
+
+        let dependencies = Dependencies {
+            ids: self.ids.into_owned(),
+            dependents: self.dependents,
+        };
+        Ok((self.resolved, dependencies))
+    }
+
+    fn visit(&mut self, node: &Node) -> Result<(), ReamError> {
+        match self.marks.get(node) {
+            Some(Mark::Done) => return Ok(()),
+            Some(Mark::Visiting) => return Err(self.cycle(node)),
+            None => {}
+        }
+        self.marks.insert(node.clone(), Mark::Visiting);
+        self.stack.push(node.clone());
+
+        // resolve the references this one depends on
+        let target = self.target(node)?;
+        for dependency in dependencies(&target) {
+            let pending = self.pending.as_ref().is_none_or(|p| p.contains(&dependency));
+            if self.is_ref(&dependency) && pending {
+                self.visit(&dependency)?;
+            }
+            let dependents = self.dependents.entry(dependency).or_default();
+            if !dependents.contains(node) {
+                dependents.push(node.clone());
+            }
+        }
+
+        let value = self.evaluate(node, &target)?;
+        self.resolved.insert(node.clone(), value);
+
+        self.stack.pop();
+        self.marks.insert(node.clone(), Mark::Done);
+        Ok(())
+    }
+
+    // identifiers must be unique within a class
+    fn index_ids(&self, entry: &'a Entry, path: &mut Path, ids: &mut HashMap<(String, String), Path>) -> Result<(), ReamError> {
+        if let Some(id) = entry.id() {
+            let k = (entry.class(), id.clone());
+            if let Some(first) = ids.get(&k) {
+                return Err(ReamError::ReferenceError(ReferenceErrorType::DuplicateIdentifier(
+                    IdentifierConflict {
+                        class: entry.class(),
+                        id: id.clone(),
+                        first: self.entry(first).position(),
+                        second: entry.position(),
+                    }
+                )));
+            }
+            ids.insert(k, path.clone());
+        }
+        for (i, subentry) in entry.subentries().iter().enumerate() {
+            path.push(i);
+            self.index_ids(subentry, path, ids)?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    fn check_foreign_keys(&self, entry: &Entry) -> Result<(), ReamError> {
+        self.check_foreign_keys_of(entry)?;
+        for subentry in entry.subentries() {
+            self.check_foreign_keys(subentry)?;
+        }
+        Ok(())
+    }
+
+    // every value of a foreign key must identify an entry of the class
+    fn check_foreign_keys_of(&self, entry: &Entry) -> Result<(), ReamError> {
+        for fk in entry.foreign_keys() {
+            let value = match entry.value(&fk.key) {
+                Some(value) => value,
+                None => continue,
+            };
+            let items = match value.get_base() {
+                ValueBase::List(list) => list.items().clone(),
+                _ => vec![value.clone()],
+            };
+            for item in items.iter().filter(|item| !item.is_na()) {
+                if !self.ids.contains_key(&(fk.class.clone(), item.get_raw())) {
+                    return Err(ReamError::ReferenceError(
+                        ReferenceErrorType::DanglingForeignKey(ReferenceSite {
+                            class: entry.class(),
+                            key: fk.key.clone(),
+                            reference: format!("{}[{}]", fk.class, item.get_raw()),
+                            span: item.span(),
+                        })
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(&self, path: &[usize]) -> &'a Entry {
+        match self.root.entry_at(path) {
+            Some(entry) => entry,
+            None => unreachable!(),
+        }
+    }
+
+    fn is_ref(&self, (path, key): &Node) -> bool {
+        self.entry(path).is_ref_key(key)
+    }
+
+    // current value of a variable, resolved if it is a reference
+    fn value(&self, node: &Node) -> &Value {
+        if let Some(value) = self.resolved.get(node) {
+            return value;
+        }
+        let tree = match self.known {
+            Some(known) if self.is_ref(node) => known,
+            _ => self.root,
+        };
+        match tree.entry_at(&node.0).and_then(|entry| entry.value(&node.1)) {
+            Some(value) => value,
+            None => unreachable!(),
+        }
+    }
+
+    fn site(&self, (path, key): &Node) -> ReferenceSite {
+        let entry = self.entry(path);
+        let value = match entry.value(key) {
+            Some(v) => v,
+            None => unreachable!(),
+        };
+        let reference = value.get_raw();
+        ReferenceSite {
+            class: entry.class(),
+            key: key.clone(),
+            reference,
+            span: value.span(),
+        }
+    }
+
+    fn cycle(&self, node: &Node) -> ReamError {
+        let start = self.stack.iter().position(|n| n == node).unwrap_or(0);
+        let sites = self.stack[start..].iter().map(|n| self.site(n)).collect();
+        ReamError::ReferenceError(ReferenceErrorType::CyclicReference(sites))
+    }
+
+    fn target(&self, node: &Node) -> Result<Target, ReamError> {
+        let (path, key) = node;
+        match self.entry(path).value(key).map(|v| v.get_base()) {
+            Some(ValueBase::Ref(r)) => self.reference_target(node, r),
+            Some(ValueBase::Expr(expression)) => {
+                let mut targets = Vec::new();
+                for operand in expression.operands() {
+                    let target = match operand {
+                        Operand::Key(k) => {
+                            if self.entry(path).value(k).is_none() {
+                                return Err(ReamError::ReferenceError(
+                                    ReferenceErrorType::VariableKeyNotFound(self.site(node))
+                                ));
+                            }
+                            Target::Sibling((path.clone(), k.clone()))
+                        }
+                        Operand::Ref(r) => self.reference_target(node, r.clone())?,
+                    };
+                    targets.push(target);
+                }
+                Ok(Target::Computed(targets))
+            }
+            _ => Err(ReamError::ReferenceError(
+                ReferenceErrorType::InvalidReference(self.site(node))
+            )),
+        }
+    }
+
+    fn reference_target(&self, node: &Node, reference: Reference) -> Result<Target, ReamError> {
+        let path = &node.0;
+        let Reference { class: ref_class, id: ref_id, key: ref_key, aggregate } = reference;
+
+        // aggregated: every descendant of the class, possibly none
+        if let Some(aggregate) = aggregate {
+            let mut paths = Vec::new();
+            collect_descendants(self.entry(path), &ref_class, &mut path.clone(), &mut paths);
+            let ref_key = match ref_key {
+                Some(k) => k,
+                None => return Ok(Target::Counted(paths.len())),
+            };
+            let mut targets = Vec::new();
+            for p in paths {
+                if self.entry(&p).value(&ref_key).is_none() {
+                    return Err(ReamError::ReferenceError(
+                        ReferenceErrorType::VariableKeyNotFound(self.site(node))
+                    ));
+                }
+                targets.push((p, ref_key.clone()));
+            }
+            return Ok(Target::Aggregated(aggregate, targets));
+        }
+
+        let ref_key = match ref_key {
+            Some(k) => k,
+            None => return Err(ReamError::ReferenceError(
+                ReferenceErrorType::InvalidReference(self.site(node))
+            )),
+        };
+
+        // anywhere: the entry with the identifier
+        if let Some(id) = ref_id {
+            let target_path = match self.ids.get(&(ref_class, id)) {
+                Some(p) => p.clone(),
+                None => return Err(ReamError::ReferenceError(
+                    ReferenceErrorType::IdentifierNotFound(self.site(node))
+                )),
+            };
+            if self.entry(&target_path).value(&ref_key).is_none() {
+                return Err(ReamError::ReferenceError(
+                    ReferenceErrorType::VariableKeyNotFound(self.site(node))
+                ));
+            }
+            return Ok(Target::Identified((target_path, ref_key)));
+        }
+
+        // upstream: the nearest ancestor of the class
+        for i in (0..path.len()).rev() {
+            let ancestor = self.entry(&path[..i]);
+            if ancestor.class() == ref_class {
+                if ancestor.value(&ref_key).is_none() {
+                    return Err(ReamError::ReferenceError(
+                        ReferenceErrorType::VariableKeyNotFound(self.site(node))
+                    ));
+                }
+                return Ok(Target::Upstream((path[..i].to_vec(), ref_key)));
+            }
+        }
+
+        // downstream: every descendant of the class
+        let mut paths = Vec::new();
+        collect_descendants(self.entry(path), &ref_class, &mut path.clone(), &mut paths);
+        if paths.is_empty() {
+            return Err(ReamError::ReferenceError(
+                ReferenceErrorType::EntryClassNotFound(self.site(node))
+            ));
+        }
+        let mut targets = Vec::new();
+        for p in paths {
+            if self.entry(&p).value(&ref_key).is_none() {
+                return Err(ReamError::ReferenceError(
+                    ReferenceErrorType::VariableKeyNotFound(self.site(node))
+                ));
+            }
+            targets.push((p, ref_key.clone()));
+        }
+        Ok(Target::Downstream(targets))
+    }
+
+    fn evaluate(&self, node: &Node, target: &Target) -> Result<Value, ReamError> {
+        let site = match self.entry(&node.0).value(&node.1) {
+            Some(v) => v,
+            None => unreachable!(),
+        };
+
+        let (value_base, typ) = self.evaluate_target(node, target)?;
+
+        // computed values must have the declared type, if any
+        if let ValueBase::Expr(expression) = site.get_base() {
+            let typ = match site.typ().merge(&typ) {
+                Some(typ) => typ,
+                None => return Err(ReamError::ExpressionError(
+                    ExpressionErrorType::IncompatibleTypes(Box::new(ReferenceTypeConflict {
+                        site: self.site(node),
+                        expected: site.typ().clone(),
+                        found: typ,
+                    }))
+                )),
+            };
+            let provenance = Provenance::Computed(expression.source().to_string());
+            return Ok(Value::new(value_base, site.get_annotation(), typ, site.span())
+                .with_provenance(provenance)
+                .with_unit(site.unit().cloned()));
+        }
+
+        // typed references, e.g. `(ref num)`, must resolve to a value of the type
+        let typ = match site.typ().clone() {
+            ValueType::Ref(expected) => match expected.merge(&typ) {
+                Some(typ) => typ,
+                None => return Err(ReamError::ReferenceError(
+                    ReferenceErrorType::IncompatibleTypes(Box::new(ReferenceTypeConflict {
+                        site: self.site(node),
+                        expected: *expected,
+                        found: typ,
+                    }))
+                )),
+            },
+            _ => typ,
+        };
+
+        match target {
+            Target::Upstream(t) | Target::Identified(t) | Target::Sibling(t) => {
+                // the annotation of the reference, if any, or of the value it points to
+                let source = self.referenced(t);
+                let annotation = site.get_annotation().or_else(|| source.get_annotation());
+                let unit = site.unit().or_else(|| source.unit()).cloned();
+                Ok(Value::new(value_base, annotation, typ, site.span())
+                    .with_provenance(source.provenance().clone())
+                    .with_unit(unit))
+            }
+            _ => Ok(Value::new(value_base, site.get_annotation(), typ, site.span()).with_unit(site.unit().cloned())),
+        }
+    }
+
+    // the value at `target`, marked with where it comes from
+    fn referenced(&self, target: &Node) -> Value {
+        let value = self.value(target);
+        let origin = match value.origin() {
+            Some(origin) => origin.clone(),
+            None => Origin {
+                class: self.entry(&target.0).class(),
+                key: target.1.clone(),
+                line: value.span().start().line(),
+            },
+        };
+        value.clone().with_provenance(Provenance::Referenced(origin))
+    }
+
+    fn evaluate_target(&self, node: &Node, target: &Target) -> Result<(ValueBase, ValueType), ReamError> {
+        let evaluated = match target {
+            Target::Upstream(t) | Target::Identified(t) | Target::Sibling(t) => self.value(t).get_base_and_typ(),
+            Target::Downstream(ts) => {
+                let items = ts.iter().map(|t| self.referenced(t)).collect();
+                let (value_base, item_typ) = List::set_list(items)?;
+                (value_base, ValueType::List(Box::new(item_typ)))
+            }
+            Target::Aggregated(aggregate, ts) => self.aggregate(node, *aggregate, ts)?,
+            Target::Counted(n) => (ValueBase::Num(Number::from(*n as i64)), ValueType::Int),
+            Target::Computed(ts) => {
+                let expression = match self.entry(&node.0).value(&node.1).map(|v| v.get_base()) {
+                    Some(ValueBase::Expr(expression)) => expression,
+                    _ => unreachable!(),
+                };
+                let mut values = Vec::new();
+                for t in ts {
+                    values.push(self.evaluate_target(node, t)?);
+                }
+                match expression.evaluate(&values) {
+                    Ok(evaluated) => evaluated,
+                    Err(EvalError::Overflow) => return Err(ReamError::TypeError(TypeErrorType::InvalidNumber)),
+                    Err(EvalError::Mismatch(mismatch)) => return Err(ReamError::ExpressionError(
+                        ExpressionErrorType::IncompatibleTypes(Box::new(ReferenceTypeConflict {
+                            site: self.site(node),
+                            expected: mismatch.expected,
+                            found: mismatch.found,
+                        }))
+                    )),
+                }
+            }
+        };
+        Ok(evaluated)
+    }
+
+    // missing values are left out
+    fn aggregate(&self, node: &Node, aggregate: Aggregate, targets: &[Node]) -> Result<(ValueBase, ValueType), ReamError> {
+        let values: Vec<&Value> = targets
+            .iter()
+            .map(|t| self.value(t))
+            .filter(|v| !v.is_na())
+            .collect();
+
+        // the earliest or latest of dates
+        let dated = values.first().is_some_and(|v| v.typ().is_date());
+        let expected = match aggregate {
+            Aggregate::Count => ValueType::Unknown,
+            Aggregate::Any => ValueType::Bool,
+            Aggregate::Min | Aggregate::Max if dated => values[0].typ().clone(),
+            _ => ValueType::Num,
+        };
+        for value in &values {
+            if expected.merge(value.typ()).is_none() {
+                return Err(ReamError::ReferenceError(
+                    ReferenceErrorType::IncompatibleTypes(Box::new(ReferenceTypeConflict {
+                        site: self.site(node),
+                        expected,
+                        found: value.typ().clone(),
+                    }))
+                ));
+            }
+        }
+
+        let numbers: Vec<Decimal> = values
+            .iter()
+            .map(|v| v.as_decimal().unwrap_or_else(Decimal::zero))
+            .collect();
+        let dates: Vec<Option<(i64, u32)>> = values
+            .iter()
+            .map(|v| date_key(&v.get_raw(), &expected))
+            .collect();
+        // index of the first smallest or largest number or date
+        let extreme = |max: bool| {
+            let mut best: Option<usize> = None;
+            for i in 0..values.len() {
+                let better = match best {
+                    None => true,
+                    Some(b) if dated && max => dates[i] > dates[b],
+                    Some(b) if dated => dates[i] < dates[b],
+                    Some(b) if max => numbers[i] > numbers[b],
+                    Some(b) => numbers[i] < numbers[b],
+                };
+                if better {
+                    best = Some(i);
+                }
+            }
+            best
+        };
+
+        let bounded = values.iter().any(|v| v.as_interval().is_some());
+        let value_base = match aggregate {
+            // bound by bound, with a point only if every value has one
+            Aggregate::Sum | Aggregate::Mean | Aggregate::Min | Aggregate::Max if bounded => {
+                let bounds: Vec<(Decimal, Decimal, Option<Decimal>)> = values.iter().filter_map(|v| v.bounds()).collect();
+                let lowers: Vec<Decimal> = bounds.iter().map(|b| b.0).collect();
+                let uppers: Vec<Decimal> = bounds.iter().map(|b| b.1).collect();
+                let points: Option<Vec<Decimal>> = bounds.iter().map(|b| b.2).collect();
+                let point = match points {
+                    Some(p) => combine(aggregate, &p)?,
+                    None => None,
+                };
+                match combine(aggregate, &lowers)?.zip(combine(aggregate, &uppers)?) {
+                    Some((lower, upper)) => ValueBase::Interval(Box::new(Interval::new(
+                        Number::from_decimal(lower),
+                        Number::from_decimal(upper),
+                        point.map(Number::from_decimal),
+                    ))),
+                    None => ValueBase::Na(None),
+                }
+            }
+            Aggregate::Count => ValueBase::Num(Number::from(values.len() as i64)),
+            Aggregate::Any => {
+                let any = values.iter().any(|v| v.get_raw() == "TRUE");
+                ValueBase::Bool(if any { "TRUE" } else { "FALSE" }.to_string())
+            }
+            // exact sums of decimals
+            Aggregate::Sum => ValueBase::Num(Number::from_decimal(sum(&numbers)?)),
+            Aggregate::Mean => match combine(aggregate, &numbers)? {
+                Some(mean) => ValueBase::Num(Number::from_decimal(mean)),
+                None => ValueBase::Na(None),
+            },
+            // the value as written, e.g. `1.50`
+            Aggregate::Min | Aggregate::Max => match extreme(aggregate == Aggregate::Max) {
+                Some(i) => values[i].get_base(),
+                None => ValueBase::Na(None),
+            },
+        };
+
+        // sums, minima and maxima of integers are integers
+        let integral = !values.is_empty() && values.iter().all(|v| *v.typ() == ValueType::Int);
+        let typ = match aggregate {
+            Aggregate::Any => ValueType::Bool,
+            Aggregate::Count => ValueType::Int,
+            Aggregate::Min | Aggregate::Max if dated => expected,
+            Aggregate::Sum | Aggregate::Min | Aggregate::Max if integral => ValueType::Int,
+            _ => ValueType::Num,
+        };
+        Ok((value_base, typ))
+    }
+}
+
+// the sum, mean, minimum or maximum of numbers; `None` if there are none
+fn combine(aggregate: Aggregate, numbers: &[Decimal]) -> Result<Option<Decimal>, ReamError> {
+    let combined = match aggregate {
+        _ if numbers.is_empty() => None,
+        Aggregate::Sum => Some(sum(numbers)?),
+        Aggregate::Mean => sum(numbers)?.div(&Number::from(numbers.len() as i64).decimal()),
+        Aggregate::Min => numbers.iter().min().copied(),
+        Aggregate::Max => numbers.iter().max().copied(),
+        Aggregate::Count | Aggregate::Any => None,
+    };
+    Ok(combined)
+}
+
+// exact, or rounded if too many digits; an error beyond the range of `f64`
+fn sum(numbers: &[Decimal]) -> Result<Decimal, ReamError> {
+    match numbers.iter().try_fold(Decimal::zero(), |acc, n| acc.add(n)) {
+        Some(sum) => Ok(sum),
+        None => Err(ReamError::TypeError(TypeErrorType::InvalidNumber)),
+    }
+}
+
+// variables a target reads, resolved first if they hold references
+fn dependencies(target: &Target) -> Vec<Node> {
+    match target {
+        Target::Upstream(t) | Target::Identified(t) | Target::Sibling(t) => vec![t.clone()],
+        Target::Downstream(ts) | Target::Aggregated(_, ts) => ts.clone(),
+        Target::Counted(_) => Vec::new(),
+        Target::Computed(targets) => targets.iter().flat_map(dependencies).collect(),
+    }
+}
+
+fn collect_ref_nodes(entry: &Entry, path: &mut Path, output: &mut Vec<Node>) {
+    for key in entry.keys() {
+        if entry.is_ref_key(&key) {
+            output.push((path.clone(), key));
+        }
+    }
+    for (i, subentry) in entry.subentries().iter().enumerate() {
+        path.push(i);
+        collect_ref_nodes(subentry, path, output);
+        path.pop();
+    }
+}
+
+fn collect_descendants(entry: &Entry, class: &str, path: &mut Path, output: &mut Vec<Path>) {
+    for (i, subentry) in entry.subentries().iter().enumerate() {
+        path.push(i);
+        if subentry.class() == class {
+            output.push(path.clone());
+        }
+        collect_descendants(subentry, class, path, output);
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::parser::Parser;
+    use super::*;
+
+    #[test]
+    fn upstream() {
+        let text = "# Country\n- name: Belgium\n\n## Language\n- country (ref): Country$name\n- name: Dutch";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "Belgium,Belgium,Dutch\n");
+    }
+
+    #[test]
+    fn downstream_within_subtree() {
+        let text = "# World\n\n## Country\n- name: Belgium\n- languages (ref): Language$name\n\n### Language\n- name: Dutch\n\n### Language\n- name: French\n\n## Country\n- name: Chile\n- languages (ref): Language$name\n\n### Language\n- name: Spanish";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(
+            entry.to_csv_str().unwrap(),
+            "Belgium,Dutch;French,Dutch\nBelgium,Dutch;French,French\nChile,Spanish,Spanish\n"
+        );
+    }
+
+    #[test]
+    fn reference_to_reference() {
+        // `Country$languages` is resolved before `Language$all` regardless of order
+        let text = "# Country\n- languages (ref): Language$name\n\n## Language\n- name: Dutch\n- all (ref): Country$languages";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "Dutch,Dutch,Dutch\n");
+    }
+
+    #[test]
+    fn reference_by_identifier() {
+        let text = "# World\n\n@@ ID_KEY(iso)\n## Country\n- iso: BEL\n- capital: Brussels\n\n## Country\n- iso: FRA\n- capital: Paris\n\n@@ ID(T1)\n## Treaty\n- capital (ref): Country[FRA]$capital";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(entry.entry_at(&[2]).unwrap().id(), Some(&"T1".to_string()));
+        let capital = entry.entry_at(&[2]).unwrap().value(&"capital".to_string()).unwrap();
+        assert_eq!(capital.get_raw(), "Paris");
+    }
+
+    #[test]
+    fn identifier_not_found() {
+        let text = "# World\n\n@@ ID(BEL)\n## Country\n- capital: Brussels\n\n## Treaty\n- capital (ref): Country[FRA]$capital";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::ReferenceError(ReferenceErrorType::IdentifierNotFound(_)))
+        ));
+    }
+
+    #[test]
+    fn duplicate_identifier() {
+        let text = "# World\n\n@@ ID_KEY(iso)\n## Country\n- iso: BEL\n\n## Country\n- iso: BEL";
+        let mut parser = Parser::new(&text);
+        match parser.parse_entry() {
+            Err(ReamError::ReferenceError(ReferenceErrorType::DuplicateIdentifier(c))) => {
+                assert_eq!(c.id, "BEL");
+                assert_eq!(c.first.line(), 4);
+                assert_eq!(c.second.line(), 7);
+            }
+            r => panic!("expected duplicate identifier, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn cyclic_reference() {
+        let text = "# Country\n- a (ref): Language$b\n\n## Language\n- b (ref): Country$a";
+        let mut parser = Parser::new(&text);
+        match parser.parse_entry() {
+            Err(ReamError::ReferenceError(ReferenceErrorType::CyclicReference(sites))) => {
+                let keys: Vec<String> = sites.iter().map(|s| s.key.clone()).collect();
+                assert_eq!(keys, vec!["a", "b"]);
+            }
+            r => panic!("expected cyclic reference, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn unresolved_reference() {
+        let text = "# Country\n- name: Belgium\n- capital (ref): City$name";
+        let mut parser = Parser::new(&text);
+        match parser.parse_entry() {
+            Err(ReamError::ReferenceError(ReferenceErrorType::EntryClassNotFound(site))) => {
+                assert_eq!(site.reference, "City$name");
+                assert_eq!(site.span.start().line(), 3);
+                assert_eq!(site.span.start().col(), 18);
+            }
+            r => panic!("expected unresolved reference, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn foreign_key() {
+        let text = "# World
+
+@@ ID_KEY(iso)
+## Country
+- iso: BEL
+- capital: Brussels
+
+@@ FOREIGN_KEY(country, Country)
+## City
+- country: BEL
+- name: Antwerp";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "BEL,Brussels
+BEL,Antwerp
+");
+        let options = CsvOptions { join: true, ..CsvOptions::default() };
+        assert_eq!(
+            entry.to_csv_str_with(&options).unwrap(),
+            "BEL,Brussels\nBEL,BEL,Brussels,Antwerp\n"
+        );
+    }
+
+    #[test]
+    fn foreign_key_join_one_level() {
+        let text = "# Family
+
+@@ ID_KEY(name)
+@@ FOREIGN_KEY(parent, Person)
+## Person
+- name: Ann
+- parent: Bob
+
+@@ ID_KEY(name)
+@@ FOREIGN_KEY(parent, Person)
+## Person
+- name: Bob
+- parent: Ann";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        let options = CsvOptions { join: true, ..CsvOptions::default() };
+        assert_eq!(entry.to_csv_str_with(&options).unwrap(), "Ann,Bob,Bob,Ann\nBob,Ann,Ann,Bob\n");
+
+        // referenced entries with foreign keys of their own keep the width of their class
+        let text = "# World
+
+@@ ID_KEY(iso)
+@@ FOREIGN_KEY(continent, Continent)
+## Country
+- iso: BEL
+- continent: EU
+
+@@ ID_KEY(code)
+## Continent
+- code: EU
+
+@@ FOREIGN_KEY(country, Country)
+## City
+- country: BEL
+- name: Antwerp";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(
+            entry.to_csv_str_with(&options).unwrap(),
+            "BEL,EU,EU\nEU\nBEL,BEL,EU,Antwerp\n"
+        );
+    }
+
+    #[test]
+    fn dangling_foreign_key() {
+        let text = "# World
+
+@@ ID_KEY(iso)
+## Country
+- iso: BEL
+
+@@ FOREIGN_KEY(countries, Country)
+## Treaty
+- countries (list str):
+  * BEL
+  * FRA";
+        let mut parser = Parser::new(&text);
+        match parser.parse_entry() {
+            Err(ReamError::ReferenceError(ReferenceErrorType::DanglingForeignKey(site))) => {
+                assert_eq!(site.reference, "Country[FRA]");
+                assert_eq!(site.span.start().line(), 11);
+            }
+            r => panic!("expected dangling foreign key, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn typed_reference() {
+        let text = "# Country
+- population: 11
+
+## Language
+- population (ref num): Country$population
+- names (ref list str): Dialect$name
+
+### Dialect
+- name: Brabantian";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        let language = entry.entry_at(&[0]).unwrap();
+        assert_eq!(language.value(&"population".to_string()).unwrap().typ(), &ValueType::Num);
+        assert_eq!(
+            language.value(&"names".to_string()).unwrap().typ(),
+            &ValueType::List(Box::new(ValueType::Str))
+        );
+    }
+
+    #[test]
+    fn typed_reference_mismatch() {
+        let text = "# Country
+- name: Belgium
+
+## Language
+- size (ref num): Country$name";
+        let mut parser = Parser::new(&text);
+        match parser.parse_entry() {
+            Err(ReamError::ReferenceError(ReferenceErrorType::IncompatibleTypes(c))) => {
+                assert_eq!(c.expected, ValueType::Num);
+                assert_eq!(c.found, ValueType::Str);
+                assert_eq!(c.site.key, "size");
+            }
+            r => panic!("expected incompatible types, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn aggregation() {
+        let text = "# Country
+- languages (ref): count(Language)
+- speakers (ref num): sum(Language$speakers)
+- mean (ref): mean(Language$speakers)
+- min (ref): min(Language$speakers)
+- max (ref): max(Language$speakers)
+- official (ref): any(Language$official)
+
+## Language
+- speakers: 6.5
+- official: TRUE
+
+## Language
+- speakers: 4.5
+- official: FALSE
+
+## Language
+- speakers: NA
+- official: FALSE";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        let values: Vec<String> = entry
+            .keys()
+            .iter()
+            .map(|k| entry.value(k).unwrap().get_raw())
+            .collect();
+        assert_eq!(values, vec!["3", "11", "5.5", "4.5", "6.5", "TRUE"]);
+        assert_eq!(entry.value(&"official".to_string()).unwrap().typ(), &ValueType::Bool);
+    }
+
+    #[test]
+    fn aggregation_without_descendants() {
+        let text = "# World
+
+## Country
+- languages (ref): count(Language)
+- mean (ref): mean(Language$speakers)
+
+## Country
+- languages (ref): count(Language)
+- mean (ref): mean(Language$speakers)
+
+### Language
+- speakers: 2";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "0,NA\n1,2,2\n");
+    }
+
+    #[test]
+    fn aggregation_type_mismatch() {
+        let text = "# Country
+- total (ref): sum(Language$name)
+
+## Language
+- name: Dutch";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::ReferenceError(ReferenceErrorType::IncompatibleTypes(_)))
+        ));
+    }
+
+    #[test]
+    fn computed_variables() {
+        let text = "# Country
+- name: Belgium
+- capital: Brussels
+- population: 11500000
+- area: 30000
+- density (num) = population / area
+- label = name + \" (\" + capital + \")\"
+- size = if count(Region) > 2 then \"large\" else \"small\"
+
+## Region
+- name: Flanders
+
+## Region
+- name: Wallonia";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        let density = entry.value(&"density".to_string()).unwrap();
+        assert_eq!(density.get_raw(), "383.3333333333333");
+        assert_eq!(density.provenance(), &Provenance::Computed("population / area".to_string()));
+        assert_eq!(entry.value(&"label".to_string()).unwrap().get_raw(), "Belgium (Brussels)");
+        assert_eq!(entry.value(&"size".to_string()).unwrap().get_raw(), "small");
+        assert_eq!(entry.value(&"name".to_string()).unwrap().provenance(), &Provenance::Explicit);
+        // computed values are written back as expressions
+        assert!(entry.to_string().contains("- density (num) = population / area\n"));
+    }
+
+    #[test]
+    fn computed_from_computed() {
+        let text = "# Country
+- b = a * 2
+- a = 1 + 1
+
+## City
+- c = Country$b + 1";
+        let mut parser = Parser::new(&text);
+        let entry = parser.parse_entry().unwrap().unwrap();
+        assert_eq!(entry.to_csv_str().unwrap(), "4,2,5\n");
+    }
+
+    #[test]
+    fn computed_type_mismatch() {
+        let text = "# Country
+- name: Belgium
+- double = name * 2";
+        let mut parser = Parser::new(&text);
+        match parser.parse_entry() {
+            Err(ReamError::ExpressionError(ExpressionErrorType::IncompatibleTypes(c))) => {
+                assert_eq!(c.expected, ValueType::Num);
+                assert_eq!(c.found, ValueType::Str);
+            }
+            r => panic!("expected incompatible types, got {:?}", r),
+        }
+
+        let text = "# Country
+- area: 30000
+- large (num) = area > 10000";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::ExpressionError(ExpressionErrorType::IncompatibleTypes(_)))
+        ));
+    }
+
+    #[test]
+    fn computed_errors() {
+        let text = "# Country
+- a = (1 + ";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::ExpressionError(ExpressionErrorType::InvalidExpression(_)))
+        ));
+
+        let text = "# Country
+- a = b + 1";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::ReferenceError(ReferenceErrorType::VariableKeyNotFound(_)))
+        ));
+
+        let text = "# Country
+- a = b + 1
+- b = a + 1";
+        let mut parser = Parser::new(&text);
+        assert!(matches!(
+            parser.parse_entry(),
+            Err(ReamError::ReferenceError(ReferenceErrorType::CyclicReference(_)))
+        ));
+    }
+
+    #[test]
+    fn referenced_annotations() {
+        let text = "# Country\n- name: Belgium\n  > official name in Dutch: Belgie\n- languages (ref): Language$name\n\n## Language\n- name: Dutch\n  > spoken in Flanders\n- country (ref): Country$name\n- noted (ref): Country$name\n  > see the constitution\n\n### Dialect\n- again (ref): Language$country";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let language = entry.entry_at(&[0]).unwrap();
+
+        let country = language.value(&"country".to_string()).unwrap();
+        assert_eq!(country.get_annotation().unwrap(), "official name in Dutch: Belgie");
+        assert_eq!(country.origin().unwrap().to_string(), "Country$name@2");
+
+        // references to references point to the original value
+        let dialect = entry.entry_at(&[0, 0]).unwrap();
+        let again = dialect.value(&"again".to_string()).unwrap();
+        assert_eq!(again.origin().unwrap().to_string(), "Country$name@2");
+
+        // the annotation of the reference is kept
+        let noted = language.value(&"noted".to_string()).unwrap();
+        assert_eq!(noted.get_annotation().unwrap(), "see the constitution");
+
+        assert_eq!(
+            entry.to_origins_str().unwrap(),
+            "class,key,line,origin\nCountry,languages,4,Language$name@7\nLanguage,country,9,Country$name@2\nLanguage,noted,10,Country$name@2\nDialect,again,14,Country$name@2\n"
+        );
+    }
+
+    #[test]
+    fn date_comparisons() {
+        let text = "# Country\n- founded (date): 1830-10-04\n- joined (date): 1957-03-25\n- member (bool) = joined > founded\n- last (date) = max(Election$held)\n\n## Election\n- held (date): 2019-05-26\n- counted (datetime): 2019-05-27T01:00+02:00\n- closed (datetime): 2019-05-26T22:30Z\n- late (bool) = counted > closed\n\n## Election\n- held (date): 2014-05-25\n- counted (datetime): 2014-05-25T23:00Z\n- closed (datetime): 2014-05-25T22:00Z\n- late (bool) = counted > closed";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        assert_eq!(entry.value(&"member".to_string()).unwrap().get_raw(), "TRUE");
+        let last = entry.value(&"last".to_string()).unwrap();
+        assert_eq!((last.get_raw(), last.typ()), ("2019-05-26".to_string(), &ValueType::Date));
+        // 01:00+02:00 is 23:00 in UTC, after 22:30
+        assert_eq!(entry.entry_at(&[0]).unwrap().value(&"late".to_string()).unwrap().get_raw(), "TRUE");
+        assert_eq!(entry.entry_at(&[1]).unwrap().value(&"late".to_string()).unwrap().get_raw(), "TRUE");
+
+        // dates are not numbers
+        let text = "# Country\n- founded (date): 1830-10-04\n- age (num) = founded + 1";
+        assert!(matches!(
+            Parser::new(&text).parse_entry(),
+            Err(ReamError::ExpressionError(ExpressionErrorType::IncompatibleTypes(_)))
+        ));
+    }
+
+    #[test]
+    fn exact_decimals() {
+        let text = "# Country\n- total = sum(Language$share)\n- third = total / 3\n- scaled = 0.1 + 0.2\n- tenth = scaled / 3\n- mean (ref): mean(Language$share)\n\n## Language\n- share: 0.59\n\n## Language\n- share: 0.41";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let value = |key: &str| entry.value(&key.to_string()).unwrap();
+        assert_eq!(value("total").get_raw(), "1");
+        assert_eq!(value("scaled").get_raw(), "0.3");
+        assert_eq!(value("scaled").as_decimal(), Decimal::parse("0.30"));
+        assert_eq!(value("third").as_f64(), Some(1.0 / 3.0));
+        // exact quotients when they terminate
+        assert_eq!(value("tenth").get_raw(), "0.1");
+        assert_eq!(value("mean").get_raw(), "0.5");
+
+        let share = entry.entry_at(&[0]).unwrap().value(&"share".to_string()).unwrap();
+        assert_eq!(share.get_raw(), "0.59");
+        assert_eq!(share.as_f64(), Some(0.59));
+        assert_eq!(share.as_i64(), None);
+        assert_eq!(value("total").as_i64(), Some(1));
+
+        // sums and products beyond the range of `f64` are invalid, not zero
+        let largest = format!("{}e270", "9".repeat(38));
+        let language = format!("## Language\n- share (num): {}", largest);
+        for text in [
+            format!("# Country\n- total = sum(Language$share)\n\n{}\n\n{}", language, language),
+            format!("# Country\n\n{}\n- double = share * 2", language),
+        ] {
+            assert!(matches!(
+                Parser::new(&text).parse_entry(),
+                Err(ReamError::TypeError(TypeErrorType::InvalidNumber))
+            ));
+        }
+    }
+
+    #[test]
+    fn aggregation_bounds() {
+        let text = "# Region
+- total (ref): sum(Country$population)
+- max (ref): max(Country$population)
+- estimated (ref): sum(Country$estimate)
+
+## Country
+- population: 2..3
+- estimate: 10 ± 1
+
+## Country
+- population: 10 ± 1
+- estimate: 5
+
+## Country
+- population: 5
+- estimate: NA";
+        let entry = Parser::new(&text).parse_entry().unwrap().unwrap();
+        let value = |key: &str| entry.value(&key.to_string()).unwrap();
+        assert_eq!(value("total").get_raw(), "16..19");
+        assert_eq!(value("max").get_raw(), "9..11");
+        assert_eq!(value("estimated").get_raw(), "15 ± 1");
+        assert_eq!(value("total").typ(), &ValueType::Int);
+    }
+}
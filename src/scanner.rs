@@ -2,6 +2,7 @@ use crate::error::*;
 use crate::format::*;
 use crate::decorator::*;
 
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::{fmt, str};
 
@@ -24,7 +25,7 @@ impl fmt::Debug for Token {
 //     }
 // }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub struct Marker {
     line: usize,
     col: usize,
@@ -34,6 +35,62 @@ impl Marker {
     pub fn new(line: usize, col: usize) -> Self {
         Marker { line, col }
    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    // the marker after `delta` lines are inserted, or `-delta` deleted, below line `line`
+    pub fn shifted(&self, line: usize, delta: isize) -> Self {
+        match self.line > line {
+            true => Marker { line: self.line.saturating_add_signed(delta), col: self.col },
+            false => *self,
+        }
+    }
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, col {}", self.line, self.col)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Span {
+    start: Marker,
+    end: Marker,
+}
+
+impl Span {
+    pub fn new(start: Marker, end: Marker) -> Self {
+        Span { start, end }
+    }
+
+    pub fn start(&self) -> Marker {
+        self.start
+    }
+
+    pub fn end(&self) -> Marker {
+        self.end
+    }
+
+    pub fn shifted(&self, line: usize, delta: isize) -> Self {
+        Span { start: self.start.shifted(line, delta), end: self.end.shifted(line, delta) }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start.line == self.end.line {
+            write!(f, "line {}, col {}-{}", self.start.line, self.start.col, self.end.col)
+        } else {
+            write!(f, "{} - {}", self.start, self.end)
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -45,11 +102,13 @@ pub enum TokenType {
 
     Value(String),
     ValueType(ValueType),
+    Unit(String),
 
     Block(usize),
     Annotation(String),
 
     Colon,
+    Equals,
     Dash,
     Star,
 
@@ -64,10 +123,16 @@ pub struct Scanner<'source> {
 
     pub eof: bool,
     pub loc: Marker,
+
+    pub types: TypeRegistry, // custom types, looked up by name
 }
 
 impl<'source> Scanner<'source> {
     pub fn new(source: &'source str) -> Self {
+        Self::with_types(source, TypeRegistry::new())
+    }
+
+    pub fn with_types(source: &'source str, types: TypeRegistry) -> Self {
         let source = source.as_bytes();
         Scanner {
             source,
@@ -76,6 +141,8 @@ impl<'source> Scanner<'source> {
             eof: false,
 
             loc: Marker::new(1, 0),
+
+            types,
         }
     }
 
@@ -109,7 +176,7 @@ impl<'source> Scanner<'source> {
         let Marker { line, col } = self.get_loc();
         let col = match &tt {
             // symbol
-            TokenType::Dash | TokenType::Colon => col,
+            TokenType::Dash | TokenType::Colon | TokenType::Equals => col,
 
             // level
             TokenType::Header(n)
@@ -275,6 +342,10 @@ impl<'source> Scanner<'source> {
 
     pub fn scan_line_list_item(&mut self) -> Result<(), ReamError> {
         self.push_token(TokenType::Star);
+        // a `*` alone opens a sub-list of the `*` items indented below it
+        if let [b'\n', ..] | [] = self.source {
+            return Ok(());
+        }
         self.skip_whitespaces(1)?;
         self.scan_value()?;
 
@@ -300,20 +371,22 @@ impl<'source> Scanner<'source> {
     }
 
     pub fn scan_token_annotation(&mut self) -> Result<(), ReamError> {
-        let mut ann = String::new();
+        let mut ann: Vec<u8> = Vec::new();
         loop {
             match self.source {
                 [b'\n', ref _rest @ ..] => {
                     break;
                 }
                 [b, ref rest @ ..] => {
-                    ann.push(*b as char);
+                    ann.push(*b);
                     self.update_source(rest);
                 }
                 _ => break, // TODO: ?
             }
         }
 
+        // UTF-8 text, e.g. `±`
+        let ann = String::from_utf8_lossy(&ann).into_owned();
         self.push_token(TokenType::Annotation(ann));
 
         Ok(())
@@ -337,13 +410,17 @@ impl<'source> Scanner<'source> {
 
     pub fn scan_line_variable(&mut self) -> Result<(), ReamError> {
         // - key (type): value
+        // - key (type) = expression
         self.push_token(TokenType::Dash);
         self.skip_whitespaces(1)?;
         self.scan_token_key()?;
         self.skip_whitespaces(0)?;
         self.scan_token_value_type()?;
         self.skip_whitespaces(0)?;
-        self.scan_token_colon()?;
+        match self.source {
+            [b'=', ..] => self.scan_token_equals()?,
+            _ => self.scan_token_colon()?,
+        }
         self.skip_whitespaces(0)?;
         self.scan_value()?;
 
@@ -353,11 +430,17 @@ impl<'source> Scanner<'source> {
     pub fn parse_unit_type(&self, t: &str) -> Result<ValueType, ReamError> {
         let typ = match t {
             "str" => ValueType::Str,
-            "num" => ValueType::Num,
+            "num" | "float" => ValueType::Num,
+            "int" => ValueType::Int,
             "bool" => ValueType::Bool,
+            "date" => ValueType::Date,
+            "datetime" => ValueType::DateTime,
+            "year" => ValueType::Year,
+            "yearmonth" => ValueType::YearMonth,
+            "cat" => ValueType::Cat(String::new()), // named by the next word
             "list" => ValueType::List(Box::new(ValueType::Unknown)),
-            // "ref" => ValueType::Ref(Box::new(ValueType::Unknown)),
-            "ref" => ValueType::Ref,
+            "ref" => ValueType::Ref(Box::new(ValueType::Unknown)),
+            name if self.types.get(name).is_some() => ValueType::Custom(name.to_string()),
             _ => return Err(ReamError::TypeError(TypeErrorType::UnknownType)),
         };
 
@@ -365,17 +448,11 @@ impl<'source> Scanner<'source> {
     }
 
     pub fn fold_types(&self, acc: ValueType, new_typ: ValueType) -> Result<ValueType, ReamError> {
+        // `list` and `ref` take the rest of the type, e.g. `list list num`
         let next = match acc {
             ValueType::Unknown => new_typ,
-            ValueType::List(_) => ValueType::List(Box::new(new_typ)),
-            // ValueType::Ref(t) => {
-            //     match *t {
-            //         ValueType::List(_) => ValueType::Ref(Box::new(ValueType::List(Box::new(new_typ)))),
-            //         _ => ValueType::Ref(Box::new(new_typ)),
-
-            //     }
-            // },
-            ValueType::Ref => ValueType::Ref,
+            ValueType::List(t) => ValueType::List(Box::new(self.fold_types(*t, new_typ)?)),
+            ValueType::Ref(t) => ValueType::Ref(Box::new(self.fold_types(*t, new_typ)?)),
             _ => {
                 return Err(ReamError::TypeError(TypeErrorType::UnknownType));
             }
@@ -384,21 +461,51 @@ impl<'source> Scanner<'source> {
         Ok(next)
     }
 
-    pub fn scan_value_type_inner(&mut self) -> Result<ValueType, ReamError> {
+    // the next word of a type, or the name of the preceding `cat`
+    fn fold_word(&self, acc: ValueType, word: &str) -> Result<ValueType, ReamError> {
+        match name_category(&acc, word) {
+            Some(typ) => Ok(typ),
+            None => self.fold_types(acc, self.parse_unit_type(word)?),
+        }
+    }
+
+    // words after a numeric type are its unit, e.g. `(num million persons)`
+    fn fold_type_or_unit(&self, acc: ValueType, unit: &mut Vec<String>, word: &str) -> Result<ValueType, ReamError> {
+        if !unit.is_empty() {
+            unit.push(word.to_string());
+            return Ok(acc);
+        }
+        match self.fold_word(acc.clone(), word) {
+            Err(_) if is_numeric(&acc) => {
+                unit.push(word.to_string());
+                Ok(acc)
+            }
+            result => result,
+        }
+    }
+
+    pub fn scan_value_type_inner(&mut self) -> Result<(ValueType, Option<String>), ReamError> {
         let mut result = ValueType::Unknown;
+        let mut unit: Vec<String> = Vec::new();
         let mut new_type_str = String::new();
         loop {
             match self.source {
                 [b')', rest @ ..] => {
                     self.update_source(rest);
-                    result =
-                        self.fold_types(result, self.parse_unit_type(new_type_str.as_str())?)?;
-                    return Ok(result);
+                    result = self.fold_type_or_unit(result, &mut unit, &new_type_str)?;
+                    if name_category(&result, "").is_some() {
+                        // `(cat)` without a name
+                        return Err(ReamError::TypeError(TypeErrorType::UnknownType));
+                    }
+                    let unit = match unit.is_empty() {
+                        true => None,
+                        false => Some(unit.join(" ")),
+                    };
+                    return Ok((result, unit));
                 }
                 [b' ', rest @ ..] => {
                     self.update_source(rest);
-                    result =
-                        self.fold_types(result, self.parse_unit_type(new_type_str.as_str())?)?;
+                    result = self.fold_type_or_unit(result, &mut unit, &new_type_str)?;
                     new_type_str = String::new();
                 }
                 [b'\n', _rest @ ..] => {
@@ -414,33 +521,42 @@ impl<'source> Scanner<'source> {
     }
 
     pub fn scan_token_value_type(&mut self) -> Result<(), ReamError> {
-        let typ: ValueType = match self.source {
+        let (typ, unit) = match self.source {
             [b'(', rest @ ..] => {
                 self.update_source(rest);
                 self.scan_value_type_inner()?
             }
-            [_, _rest @ ..] => ValueType::Unknown,
+            [_, _rest @ ..] => (ValueType::Unknown, None),
             _ => return Err(ReamError::ScanError(ScanErrorType::InvalidToken)),
         };
 
         // only known type will be pushed to buffer
-        match typ {
-            ValueType::Unknown => {}
-            t => self.push_token(TokenType::ValueType(t)),
+        match (typ, unit) {
+            (ValueType::Unknown, _) => {}
+            (t, None) => self.push_token(TokenType::ValueType(t)),
+            // `(num km2)`: the type, then the unit before `)`
+            (t, Some(unit)) => {
+                let end = self.get_loc();
+                let unit_start = Marker::new(end.line, end.col - unit.len());
+                let start = Marker::new(end.line, unit_start.col - t.size() - 2);
+                let type_end = Marker::new(end.line, start.col + t.size());
+                self.buffer.push_back(Token(TokenType::ValueType(t), start, type_end));
+                self.buffer.push_back(Token(TokenType::Unit(unit), unit_start, end));
+            }
         }
 
         Ok(())
     }
 
     pub fn scan_value(&mut self) -> Result<(), ReamError> {
-        let mut value = String::new();
+        let mut value: Vec<u8> = Vec::new();
         loop {
             match self.source {
                 [b'\n', ref _rest @ ..] => {
                     break;
                 }
                 [b, ref rest @ ..] => {
-                    value.push(*b as char);
+                    value.push(*b);
                     self.update_source(rest);
                 }
                 _ => break, // TODO: ?
@@ -450,6 +566,7 @@ impl<'source> Scanner<'source> {
         if value.is_empty() {
             return Ok(());
         }
+        let value = String::from_utf8_lossy(&value).into_owned();
 
         self.push_token(TokenType::Value(value));
 
@@ -469,6 +586,19 @@ impl<'source> Scanner<'source> {
         Ok(())
     }
 
+    pub fn scan_token_equals(&mut self) -> Result<(), ReamError> {
+        match self.source {
+            [b'=', ref rest @ ..] => {
+                self.update_source(rest);
+            }
+            _ => return Err(ReamError::ScanError(ScanErrorType::MissingColon)),
+        }
+
+        self.push_token(TokenType::Equals);
+
+        Ok(())
+    }
+
     pub fn scan_token_header(&mut self) -> Result<(), ReamError> {
         let mut count = 1;
         loop {
@@ -495,6 +625,9 @@ impl<'source> Scanner<'source> {
                 [b':', ref _rest @ ..] => {
                     break;
                 }
+                [b'=', ref _rest @ ..] => {
+                    break;
+                }
                 [b' ', ref _rest @ ..] => {
                     break;
                 }
@@ -553,6 +686,24 @@ impl<'source> Scanner<'source> {
     }
 }
 
+// `num` or `int`, including as items of a list or as a typed reference
+fn is_numeric(typ: &ValueType) -> bool {
+    match typ {
+        ValueType::List(t) | ValueType::Ref(t) => is_numeric(t),
+        t => t.is_numeric(),
+    }
+}
+
+// `typ` with its unnamed `cat` named `name`, if any
+fn name_category(typ: &ValueType, name: &str) -> Option<ValueType> {
+    match typ {
+        ValueType::Cat(n) if n.is_empty() => Some(ValueType::Cat(name.to_string())),
+        ValueType::List(t) => name_category(t, name).map(|t| ValueType::List(Box::new(t))),
+        ValueType::Ref(t) => name_category(t, name).map(|t| ValueType::Ref(Box::new(t))),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -654,6 +805,55 @@ mod tests {
         )
     }
 
+    #[test]
+    fn variable_with_type_and_unit() {
+        //          0        1
+        //          1234567890123456789
+        let text = "- area (num km2): 1";
+        let mut scanner = Scanner::new(&text);
+        let _ = scanner.scan_line();
+        assert_eq!(
+            scanner.buffer.iter().skip(2).take(2).cloned().collect::<Vec<Token>>(),
+            vec![
+                Token( // (num
+                    TokenType::ValueType(ValueType::Num),
+                    Marker { line: 1, col: 8 },
+                    Marker { line: 1, col: 11 },
+                ),
+                Token( // km2)
+                    TokenType::Unit("km2".to_string()),
+                    Marker { line: 1, col: 13 },
+                    Marker { line: 1, col: 16 },
+                ),
+            ]
+        )
+    }
+
+    #[test]
+    fn variable_with_nested_type_and_unit() {
+        //          0        1         2
+        //          123456789012345678901234567
+        let text = "- xs (list list num km2): 1";
+        let mut scanner = Scanner::new(&text);
+        let _ = scanner.scan_line();
+        let nested = ValueType::List(Box::new(ValueType::List(Box::new(ValueType::Num))));
+        assert_eq!(
+            scanner.buffer.iter().skip(2).take(2).cloned().collect::<Vec<Token>>(),
+            vec![
+                Token( // (list list num
+                    TokenType::ValueType(nested),
+                    Marker { line: 1, col: 6 },
+                    Marker { line: 1, col: 19 },
+                ),
+                Token( // km2)
+                    TokenType::Unit("km2".to_string()),
+                    Marker { line: 1, col: 21 },
+                    Marker { line: 1, col: 24 },
+                ),
+            ]
+        )
+    }
+
     #[test]
     fn count_spaces() {
         //          0        1